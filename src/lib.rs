@@ -1,171 +1,3028 @@
-use wasm_bindgen::prelude::*;
-
-// Custom error for better handling
-#[derive(Debug, PartialEq, Eq)]
-pub enum ResizeError {
-    MinItemWidthOverload,
-    LowerThanMinHeight,
-    BiggerThanMaxHeight,
-    CanNotFitItems,
-    Empty,
-}
-
-// Main struct with essentials props
-#[derive(Debug)]
-pub struct ImageGrid {
-    pub available_width: f64,
-    pub min_line_height: f64,
-    pub max_line_height: f64,
-    pub min_item_width: f64,
-    pub gap: f64,
-}
-
-impl ImageGrid {
-    pub fn new(
-        available_width: f64,
-        min_line_height: f64,
-        max_line_height: f64,
-        min_item_width: f64,
-        gap: f64,
-    ) -> Self {
-        assert!(
-            min_line_height <= max_line_height,
-            "Min height can not be bigger than max height"
-        );
-        assert!(
-            available_width >= min_item_width,
-            "Available width can not be less than min item width"
-        );
-
-        ImageGrid {
-            available_width,
-            min_line_height,
-            max_line_height,
-            min_item_width,
-            gap,
-        }
-    }
-
-    // Getting sum of all elements after multiply
-    fn calculate_all_width_by_height(
-        &self,
-        ratios: &[f64],
-        desired_height: f64,
-    ) -> f64 {
-        ratios.iter().fold(-self.gap, |acc, &ratio| {
-            acc + desired_height * ratio + self.gap
-        })
-    }
-
-    /// The calculate_all_width_by_height_secure function calculates the width of all items by a given height. The function checks that the width of the items does not exceed the available width, and that the width of the items does not exceed the minimum item width.
-    fn calculate_all_width_by_height_secure(
-        &self,
-        ratios: &[f64],
-        desired_height: f64,
-    ) -> Result<f64, ResizeError> {
-        if ratios.iter().any(|ratio| {
-            (desired_height * ratio) + self.gap
-                < self.min_item_width
-        }) {
-            return Err(ResizeError::MinItemWidthOverload);
-        }
-
-        let width = self.calculate_all_width_by_height(
-            ratios,
-            desired_height,
-        );
-
-        if width > self.available_width {
-            Err(ResizeError::CanNotFitItems)
-        } else {
-            Ok(width)
-        }
-    }
-
-    /// Check if we may fit all items with max or min line height
-    fn items_may_be_fitted(
-        &self,
-        ratios: &[f64],
-        height: f64,
-    ) -> bool {
-        self.calculate_all_width_by_height_secure(
-            ratios, height,
-        )
-        .is_ok()
-    }
-
-    /// Returns vector of tuples with number of items to take and height for them
-    pub fn get_row_from_items(
-        &self,
-        ratios: &mut Vec<f64>,
-    ) -> Vec<(u32, f64)> {
-        let mut not_fitted: Vec<f64> = Vec::new();
-        let mut height_for_ratios =
-            self.get_optimal_height(ratios);
-
-        for ratio_index in 0..ratios.len() {
-            let items = &ratios[0..=ratio_index];
-            let new_height = self.get_optimal_height(items);
-            if !self.items_may_be_fitted(items, new_height)
-            {
-                not_fitted =
-                    ratios.drain(ratio_index..).collect();
-                break;
-            }
-
-            height_for_ratios =
-                new_height.min(self.max_line_height);
-        }
-
-        let mut result =
-            vec![(ratios.len() as u32, height_for_ratios)];
-
-        if !not_fitted.is_empty() {
-            let rest_filtered = &mut self
-                .get_row_from_items(&mut not_fitted);
-            result.append(rest_filtered);
-        }
-
-        result
-    }
-
-    pub fn get_optimal_height(
-        &self,
-        ratios: &[f64],
-    ) -> f64 {
-        let gaps_width = ratios.len().saturating_sub(1)
-            as f64
-            * self.gap;
-        let total_width = self.available_width - gaps_width;
-        (total_width / ratios.iter().sum::<f64>()).floor()
-    }
-}
-
-#[wasm_bindgen]
-pub fn get_optimal_grid(
-    ratios: Vec<f64>,
-    available_width: f64,
-    min_line_height: f64,
-    max_line_height: f64,
-    min_item_width: f64,
-    gap: f64,
-) -> js_sys::Array {
-    let mut ratios = ratios;
-    let grid = ImageGrid::new(
-        available_width,
-        min_line_height,
-        max_line_height,
-        min_item_width,
-        gap,
-    );
-
-    let height_list =
-        grid.get_row_from_items(&mut ratios).into_iter();
-
-    height_list
-        .flat_map(|(count, height)| {
-            std::iter::repeat(height).take(count as usize)
-        })
-        .map(JsValue::from)
-        .collect::<js_sys::Array>()
-}
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+
+// Custom error for better handling
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ResizeError {
+    MinItemWidthOverload,
+    LowerThanMinHeight,
+    BiggerThanMaxHeight,
+    CanNotFitItems,
+    Empty,
+    /// Row breaks are not increasing indices starting at 0
+    InvalidBreaks,
+    /// A ratio is NaN while `NanPolicy::Error` is in use
+    NanRatio,
+    /// `min_line_height` is above `max_line_height`, or a bound isn't finite
+    InvalidHeightBounds,
+}
+
+impl std::fmt::Display for ResizeError {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        let message = match self {
+            ResizeError::MinItemWidthOverload => {
+                "An item is narrower than the min item width"
+            }
+            ResizeError::LowerThanMinHeight => {
+                "Row height is lower than the min line height"
+            }
+            ResizeError::BiggerThanMaxHeight => {
+                "Row height is bigger than the max line height"
+            }
+            ResizeError::CanNotFitItems => {
+                "Items can not fit the available width"
+            }
+            ResizeError::Empty => "There are no items to lay out",
+            ResizeError::InvalidBreaks => "Row breaks are invalid",
+            ResizeError::NanRatio => "A ratio is NaN",
+            ResizeError::InvalidHeightBounds => {
+                "Min line height is bigger than the max line height or a bound is not finite"
+            }
+        };
+        f.write_str(message)
+    }
+}
+
+impl std::error::Error for ResizeError {}
+
+impl From<ResizeError> for JsValue {
+    fn from(error: ResizeError) -> Self {
+        js_sys::Error::new(&error.to_string()).into()
+    }
+}
+
+/// How optimal row heights get rounded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HeightRounding {
+    /// Round down to whole pixels, rows never overflow the width
+    #[default]
+    Floor,
+    /// Round to the nearest whole pixel, rows may overflow the width by
+    /// up to half a pixel per unit of ratio
+    Round,
+    /// Keep the fractional height
+    Exact,
+}
+
+impl HeightRounding {
+    fn apply(self, height: f64) -> f64 {
+        match self {
+            HeightRounding::Floor => height.floor(),
+            HeightRounding::Round => height.round(),
+            HeightRounding::Exact => height,
+        }
+    }
+
+    /// Most a row of items summing up to `ratio_sum` may overflow the width
+    /// because of rounding
+    fn max_overflow(self, ratio_sum: f64) -> f64 {
+        match self {
+            HeightRounding::Round => 0.5 * ratio_sum,
+            HeightRounding::Floor
+            | HeightRounding::Exact => 0.0,
+        }
+    }
+}
+
+/// Rows along with the indices of the items skipped by `NanPolicy::Skip`
+pub type CheckedRows = (Vec<(u32, f64)>, Vec<usize>);
+
+/// What to do with NaN ratios before laying them out
+#[derive(
+    Debug, Clone, Copy, PartialEq, Default, Deserialize,
+)]
+pub enum NanPolicy {
+    /// Refuse the input with `ResizeError::NanRatio`
+    #[default]
+    Error,
+    /// Drop the NaN items
+    Skip,
+    /// Replace NaN with the given ratio
+    Substitute(f64),
+}
+
+/// How `get_row_from_items` meets `max_rows`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Drop the rows past `max_rows`
+    #[default]
+    Truncate,
+    /// Justify as usual, then scale the height bounds down uniformly until
+    /// every item fits in `max_rows` rows. Rows stay justified so items keep
+    /// their aspect ratio.
+    JustifyThenScale,
+}
+
+/// How the greedy layout keeps a row within the height bounds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FitPolicy {
+    /// Justify the row to fill the width. Taller than `max_line_height` it
+    /// is clamped to it (ragged right), shorter than `min_line_height` it
+    /// breaks one item earlier instead.
+    #[default]
+    ClampThenBreak,
+    /// Clamp to `max_line_height` but never break because of
+    /// `min_line_height`, only `min_item_width` and the width end a row
+    ClampOnly,
+}
+
+/// When gaps are made room for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GapComputation {
+    /// Gaps take part in the fit of every row
+    #[default]
+    Inline,
+    /// Rows are fitted as if there was no gap, positioned items then get
+    /// narrower to make room for the gaps. Breaks and heights can differ
+    /// slightly from `Inline` since rows look wider than they end up.
+    PostInset,
+}
+
+/// Height of a last row that doesn't fill the width even at
+/// `max_line_height`, applied by `get_row_from_items` and `rows_iter`
+#[derive(
+    Debug, Clone, Copy, PartialEq, Default, Deserialize,
+)]
+pub enum LastRowBehavior {
+    /// Blow it up to `max_line_height`
+    #[default]
+    Justify,
+    /// Reuse the height of the row before it
+    MatchPrevious,
+    /// Cap it at the given height
+    Clamp(f64),
+}
+
+/// How rows are sized once their items are picked
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum JustifyMode {
+    /// Stretch every row to fill the available width
+    #[default]
+    Justify,
+    /// Keep items at their natural `max_line_height`, rows are filled by
+    /// width or count and stay ragged on the right
+    None,
+}
+
+/// Position and size of a single item within the grid
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct PositionedItem {
+    /// Index of the item in the caller's data
+    pub index: usize,
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+    /// Width as a percentage of the row width left after gaps
+    pub percent_width: f64,
+}
+
+impl PositionedItem {
+    /// Whether the point falls within the item's box, right and bottom
+    /// edges excluded
+    pub fn contains(&self, px: f64, py: f64) -> bool {
+        (self.x..self.x + self.width).contains(&px)
+            && (self.y..self.y + self.height).contains(&py)
+    }
+}
+
+/// A single row of `get_grid_rows`, items `start_index..start_index + count`
+/// of the input
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct GridRow {
+    pub height: f64,
+    pub start_index: usize,
+    pub count: u32,
+}
+
+/// Result of `ImageGrid::get_fixed_column_grid`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FixedColumnLayout {
+    pub items: Vec<PositionedItem>,
+    /// Indices of the items whose height got clamped to the height bounds,
+    /// those no longer keep their aspect ratio
+    pub clamped: Vec<usize>,
+}
+
+/// Position and size of a single item, as handed to JS
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GridItemLayout {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl From<PositionedItem> for GridItemLayout {
+    fn from(item: PositionedItem) -> Self {
+        GridItemLayout {
+            x: item.x,
+            y: item.y,
+            width: item.width,
+            height: item.height,
+        }
+    }
+}
+
+/// Positioned layout stored as one array per property, in the original order
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LayoutSoA {
+    pub xs: Vec<f64>,
+    pub ys: Vec<f64>,
+    pub widths: Vec<f64>,
+    pub heights: Vec<f64>,
+}
+
+/// Grid settings passed from JS as a plain object
+#[derive(Debug, Clone, Deserialize)]
+pub struct GridConfig {
+    pub available_width: f64,
+    pub min_line_height: f64,
+    pub max_line_height: f64,
+    pub min_item_width: f64,
+    pub gap: f64,
+    #[serde(default)]
+    pub row_gap: f64,
+    /// Width comparison tolerance, `ImageGrid::DEFAULT_EPSILON` when missing
+    #[serde(default)]
+    pub epsilon: Option<f64>,
+    /// `"Justify"` when missing, `"MatchPrevious"` or `{ "Clamp": height }`
+    #[serde(default)]
+    pub last_row: LastRowBehavior,
+    /// See `ImageGrid::last_row_justify_threshold`, `0` when missing
+    #[serde(default)]
+    pub last_row_justify_threshold: f64,
+    /// `"Error"` when missing, `"Skip"` or `{ "Substitute": ratio }`
+    #[serde(default)]
+    pub nan_policy: NanPolicy,
+}
+
+impl GridConfig {
+    /// Errors like `ImageGrid::try_new` on invalid bounds
+    pub fn to_grid(
+        &self,
+    ) -> Result<ImageGrid, ResizeError> {
+        Ok(ImageGrid {
+            row_gap: self.row_gap,
+            last_row: self.last_row,
+            last_row_justify_threshold: self
+                .last_row_justify_threshold,
+            nan_policy: self.nan_policy,
+            epsilon: self
+                .epsilon
+                .unwrap_or(ImageGrid::DEFAULT_EPSILON),
+            ..ImageGrid::try_new(
+                self.available_width,
+                self.min_line_height,
+                self.max_line_height,
+                self.min_item_width,
+                self.gap,
+            )?
+        })
+    }
+}
+
+/// Diagnostics of a single row
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct RowReport {
+    pub count: u32,
+    pub height: f64,
+    /// Horizontal space left unused by the row
+    pub slack: f64,
+    /// Row was capped at `max_line_height` instead of filling the width
+    pub clamped: bool,
+}
+
+/// A constraint the layout could not satisfy, `row` is `None` for the whole input
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ConstraintViolation {
+    pub row: Option<usize>,
+    pub error: ResizeError,
+}
+
+/// Totals of a layout
+#[derive(
+    Debug, Clone, Copy, PartialEq, Default, Serialize,
+)]
+pub struct LayoutStats {
+    pub row_count: usize,
+    pub item_count: usize,
+    pub total_height: f64,
+}
+
+/// Everything known about a layout, for debugging
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LayoutReport {
+    pub rows: Vec<RowReport>,
+    pub stats: LayoutStats,
+    pub errors: Vec<ConstraintViolation>,
+    /// Input indices dropped by `NanPolicy::Skip`, rows hold the rest
+    pub skipped: Vec<usize>,
+}
+
+/// Lays out the same ratios at many widths, e.g. during a resize drag.
+/// Prefix sums are computed once so each `layout` is a single O(n) scan.
+#[derive(Debug)]
+pub struct ResizeSession<'a> {
+    grid: &'a ImageGrid,
+    ratios: &'a [f64],
+    prefix_sums: Vec<f64>,
+}
+
+impl<'a> ResizeSession<'a> {
+    pub fn new(
+        grid: &'a ImageGrid,
+        ratios: &'a [f64],
+    ) -> Self {
+        let prefix_sums = std::iter::once(0.0)
+            .chain(ratios.iter().scan(
+                0.0,
+                |sum, &ratio| {
+                    *sum += ratio;
+                    Some(*sum)
+                },
+            ))
+            .collect();
+
+        ResizeSession { grid, ratios, prefix_sums }
+    }
+
+    /// Rows for the given container width
+    pub fn layout(
+        &self,
+        available_width: f64,
+    ) -> Vec<(u32, f64)> {
+        let grid =
+            self.grid.with_available_width(available_width);
+        Rows {
+            prefix_sums: Some(&self.prefix_sums),
+            ..grid.rows_iter(self.ratios)
+        }
+        .collect()
+    }
+}
+
+/// Lazily computed rows, see `ImageGrid::rows_iter`
+#[derive(Debug, Clone)]
+pub struct Rows<'a> {
+    grid: Cow<'a, ImageGrid>,
+    ratios: &'a [f64],
+    prefix_sums: Option<&'a [f64]>,
+    start: usize,
+    max_rows: Option<usize>,
+    /// Height of the last yielded row
+    previous: Option<f64>,
+}
+
+impl Rows<'_> {
+    /// Whether every row was yielded, without laying out another one
+    fn is_done(&self) -> bool {
+        self.start >= self.ratios.len()
+            || self.max_rows == Some(0)
+    }
+}
+
+impl Iterator for Rows<'_> {
+    type Item = (u32, f64);
+
+    fn next(&mut self) -> Option<(u32, f64)> {
+        if self.is_done() {
+            return None;
+        }
+
+        let (end, height) = if self.ratios.len()
+            < self.grid.single_row_threshold as usize
+        {
+            let height = self
+                .grid
+                .get_optimal_height(self.ratios)
+                .min(self.grid.max_line_height);
+            (self.ratios.len(), height)
+        } else {
+            self.grid.next_row(
+                self.ratios,
+                self.start,
+                self.prefix_sums,
+            )
+        };
+
+        let height = if end == self.ratios.len() {
+            self.grid.last_row_height(
+                &self.ratios[self.start..end],
+                height,
+                self.previous,
+            )
+        } else {
+            height
+        };
+
+        let count = (end - self.start) as u32;
+        self.start = end;
+        self.max_rows =
+            self.max_rows.map(|max_rows| max_rows - 1);
+        self.previous = Some(height);
+        Some((count, height))
+    }
+}
+
+/// SplitMix64, a tiny PRNG giving the same sequence on every platform
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30))
+            .wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27))
+            .wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+/// Picks the height of a row once its items are known
+pub trait HeightSolver {
+    fn solve(
+        &self,
+        ratios: &[f64],
+        grid: &ImageGrid,
+    ) -> f64;
+}
+
+/// Stretches the row to fill the available width, the default behavior
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FillWidthSolver;
+
+impl HeightSolver for FillWidthSolver {
+    fn solve(
+        &self,
+        ratios: &[f64],
+        grid: &ImageGrid,
+    ) -> f64 {
+        grid.get_optimal_height(ratios)
+    }
+}
+
+/// Aims for a fixed row height, lowering it only when the row would overflow
+#[derive(Debug, Clone, Copy)]
+pub struct TargetHeightSolver {
+    pub height: f64,
+}
+
+impl HeightSolver for TargetHeightSolver {
+    fn solve(
+        &self,
+        ratios: &[f64],
+        grid: &ImageGrid,
+    ) -> f64 {
+        self.height.min(grid.get_optimal_height(ratios))
+    }
+}
+
+// Main struct with essentials props
+#[derive(Clone)]
+pub struct ImageGrid {
+    pub available_width: f64,
+    pub min_line_height: f64,
+    pub max_line_height: f64,
+    pub min_item_width: f64,
+    pub gap: f64,
+    /// Vertical space between rows
+    pub row_gap: f64,
+    /// Maximum items per row, computed from `available_width`
+    pub items_per_row_limit: Option<Rc<dyn Fn(f64) -> u32>>,
+    /// Inputs with fewer items than this always stay on a single row
+    pub single_row_threshold: u32,
+    /// Tolerance used when comparing widths against the constraints
+    pub epsilon: f64,
+    pub rounding: HeightRounding,
+    /// Rows past this count are dropped
+    pub max_rows: Option<u32>,
+    pub justify_mode: JustifyMode,
+    /// Shrinks every positioned box by this much on each side, keeping it
+    /// centered in its slot, to hide seams between adjacent images
+    pub inset: f64,
+    /// Caps the total ratio of a row: once its ratios add up to this the row
+    /// takes no more items, though it may still break earlier on width or
+    /// height. Keeps rows of narrow portraits from getting too dense.
+    pub max_ratio_sum_per_row: Option<f64>,
+    /// Applied to the input by `get_rows_checked`
+    pub nan_policy: NanPolicy,
+    /// What `get_row_from_items` does with rows past `max_rows`
+    pub overflow_policy: OverflowPolicy,
+    /// How the greedy layout reacts to rows leaving the height bounds
+    pub fit_policy: FitPolicy,
+    /// Seeds the tie-breaking of `get_rows_optimal`, equal cost breaks are then
+    /// picked at random but reproducibly. `None` keeps the stable default.
+    pub seed: Option<u64>,
+    /// Fixed cap on the items per row, combined with `items_per_row_limit`
+    pub max_items_per_row: Option<u32>,
+    /// How `gap` is accounted for while fitting rows
+    pub gap_mode: GapComputation,
+    /// Height of a last row too short to fill the width
+    pub last_row: LastRowBehavior,
+    /// Share of the width the last row has to fill at the previous row's height
+    /// before it gets stretched. Below it `last_row` applies, with `Justify`
+    /// keeping the previous row's height.
+    pub last_row_justify_threshold: f64,
+}
+
+impl Default for ImageGrid {
+    fn default() -> Self {
+        ImageGrid {
+            available_width: 0.0,
+            min_line_height: 0.0,
+            max_line_height: 0.0,
+            min_item_width: 0.0,
+            gap: 0.0,
+            row_gap: 0.0,
+            items_per_row_limit: None,
+            single_row_threshold: 0,
+            epsilon: Self::DEFAULT_EPSILON,
+            rounding: HeightRounding::default(),
+            max_rows: None,
+            justify_mode: JustifyMode::default(),
+            inset: 0.0,
+            max_ratio_sum_per_row: None,
+            nan_policy: NanPolicy::default(),
+            overflow_policy: OverflowPolicy::default(),
+            fit_policy: FitPolicy::default(),
+            seed: None,
+            max_items_per_row: None,
+            gap_mode: GapComputation::default(),
+            last_row: LastRowBehavior::default(),
+            last_row_justify_threshold: 0.0,
+        }
+    }
+}
+
+impl std::fmt::Debug for ImageGrid {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        f.debug_struct("ImageGrid")
+            .field("available_width", &self.available_width)
+            .field("min_line_height", &self.min_line_height)
+            .field("max_line_height", &self.max_line_height)
+            .field("min_item_width", &self.min_item_width)
+            .field("gap", &self.gap)
+            .field("row_gap", &self.row_gap)
+            .field(
+                "items_per_row_limit",
+                &self
+                    .items_per_row_limit
+                    .as_ref()
+                    .map(|_| "Fn(f64) -> u32"),
+            )
+            .field(
+                "single_row_threshold",
+                &self.single_row_threshold,
+            )
+            .field("epsilon", &self.epsilon)
+            .field("rounding", &self.rounding)
+            .field("max_rows", &self.max_rows)
+            .field("justify_mode", &self.justify_mode)
+            .field("inset", &self.inset)
+            .field(
+                "max_ratio_sum_per_row",
+                &self.max_ratio_sum_per_row,
+            )
+            .field("nan_policy", &self.nan_policy)
+            .field("overflow_policy", &self.overflow_policy)
+            .field("fit_policy", &self.fit_policy)
+            .field("seed", &self.seed)
+            .field(
+                "max_items_per_row",
+                &self.max_items_per_row,
+            )
+            .field("gap_mode", &self.gap_mode)
+            .field("last_row", &self.last_row)
+            .field(
+                "last_row_justify_threshold",
+                &self.last_row_justify_threshold,
+            )
+            .finish()
+    }
+}
+
+/// Step by step construction of an `ImageGrid`, see `ImageGrid::builder`
+#[derive(Debug, Default)]
+pub struct ImageGridBuilder {
+    grid: ImageGrid,
+    max_line_height_fraction: Option<f64>,
+}
+
+impl ImageGridBuilder {
+    pub fn available_width(mut self, width: f64) -> Self {
+        self.grid.available_width = width;
+        self
+    }
+
+    pub fn min_line_height(mut self, height: f64) -> Self {
+        self.grid.min_line_height = height;
+        self
+    }
+
+    pub fn max_line_height(mut self, height: f64) -> Self {
+        self.grid.max_line_height = height;
+        self.max_line_height_fraction = None;
+        self
+    }
+
+    /// `max_line_height` as a share of `available_width`, resolved in
+    /// `build`. Replaces any fixed `max_line_height`.
+    pub fn max_line_height_fraction(
+        mut self,
+        fraction: f64,
+    ) -> Self {
+        self.max_line_height_fraction = Some(fraction);
+        self
+    }
+
+    pub fn min_item_width(mut self, width: f64) -> Self {
+        self.grid.min_item_width = width;
+        self
+    }
+
+    pub fn gap(mut self, gap: f64) -> Self {
+        self.grid.gap = gap;
+        self
+    }
+
+    pub fn row_gap(mut self, row_gap: f64) -> Self {
+        self.grid.row_gap = row_gap;
+        self
+    }
+
+    pub fn build(self) -> ImageGrid {
+        let mut grid = self.grid;
+        if let Some(fraction) =
+            self.max_line_height_fraction
+        {
+            grid.max_line_height =
+                grid.available_width * fraction;
+        }
+        grid
+    }
+}
+
+impl ImageGrid {
+    /// Amount of columns used to approximate item widths in `to_css_grid`
+    pub const CSS_GRID_COLUMNS: u32 = 24;
+    /// Selector of the container rule emitted by `to_css_grid`
+    pub const CSS_GRID_CONTAINER: &'static str =
+        ".perfect-grid";
+    /// Default tolerance for width comparisons
+    pub const DEFAULT_EPSILON: f64 = 1e-9;
+    /// Decimals of the heights in `to_snapshot_string`
+    pub const SNAPSHOT_DECIMALS: usize = 2;
+    /// Bisection steps of `OverflowPolicy::JustifyThenScale`
+    const SCALE_SEARCH_STEPS: u32 = 32;
+    /// Most widths tried by `width_for_full_last_row`
+    pub const FULL_LAST_ROW_SEARCH_STEPS: u64 = 4096;
+    /// Highest `max_line_height` tried by `fit_grid_aspect`
+    const LINE_HEIGHT_SEARCH_LIMIT: f64 = 1e6;
+
+    /// Same as `try_new`, panics on invalid bounds
+    pub fn new(
+        available_width: f64,
+        min_line_height: f64,
+        max_line_height: f64,
+        min_item_width: f64,
+        gap: f64,
+    ) -> Self {
+        match Self::try_new(
+            available_width,
+            min_line_height,
+            max_line_height,
+            min_item_width,
+            gap,
+        ) {
+            Ok(grid) => grid,
+            Err(ResizeError::InvalidHeightBounds) => {
+                panic!("Min height can not be bigger than max height")
+            }
+            Err(_) => panic!(
+                "Available width can not be less than min item width"
+            ),
+        }
+    }
+
+    /// Errors with `InvalidHeightBounds` when `min_line_height` is above
+    /// `max_line_height` or any argument isn't finite, and
+    /// `MinItemWidthOverload` when `available_width` is below `min_item_width`
+    pub fn try_new(
+        available_width: f64,
+        min_line_height: f64,
+        max_line_height: f64,
+        min_item_width: f64,
+        gap: f64,
+    ) -> Result<Self, ResizeError> {
+        let finite = [
+            available_width,
+            min_line_height,
+            max_line_height,
+            min_item_width,
+            gap,
+        ]
+        .iter()
+        .all(|value| value.is_finite());
+        if !finite || min_line_height > max_line_height {
+            return Err(ResizeError::InvalidHeightBounds);
+        }
+        if available_width < min_item_width {
+            return Err(ResizeError::MinItemWidthOverload);
+        }
+
+        Ok(ImageGrid {
+            available_width,
+            min_line_height,
+            max_line_height,
+            min_item_width,
+            gap,
+            ..Default::default()
+        })
+    }
+
+    pub fn builder() -> ImageGridBuilder {
+        ImageGridBuilder::default()
+    }
+
+    /// Max amount of items allowed in a single row
+    fn row_item_limit(&self) -> usize {
+        let computed = self
+            .items_per_row_limit
+            .as_ref()
+            .map_or(usize::MAX, |limit| {
+                limit(self.available_width).max(1) as usize
+            });
+        let fixed = self
+            .max_items_per_row
+            .map_or(usize::MAX, |max| max.max(1) as usize);
+
+        computed.min(fixed)
+    }
+
+    /// Copy of the grid laid out against another container width
+    fn with_available_width(
+        &self,
+        available_width: f64,
+    ) -> ImageGrid {
+        ImageGrid { available_width, ..self.clone() }
+    }
+
+    /// Copy of the grid with another `max_line_height`
+    fn with_max_line_height(
+        &self,
+        max_line_height: f64,
+    ) -> ImageGrid {
+        ImageGrid { max_line_height, ..self.clone() }
+    }
+
+    /// Height of the given rows stacked with `row_gap` between them
+    fn rows_total_height(
+        &self,
+        rows: &[(u32, f64)],
+    ) -> f64 {
+        rows.iter().map(|&(_, height)| height).sum::<f64>()
+            + rows.len().saturating_sub(1) as f64
+                * self.row_gap
+    }
+
+    // Getting sum of all elements after multiply
+    fn calculate_all_width_by_height(
+        &self,
+        ratios: &[f64],
+        desired_height: f64,
+    ) -> f64 {
+        ratios.iter().fold(-self.gap, |acc, &ratio| {
+            acc + desired_height * ratio + self.gap
+        })
+    }
+
+    /// Width of the given items laid side by side at `height`, gaps between
+    /// them included. No constraint is checked.
+    pub fn row_width_at_height(
+        &self,
+        ratios: &[f64],
+        height: f64,
+    ) -> f64 {
+        self.calculate_all_width_by_height(ratios, height)
+            .max(0.0)
+    }
+
+    /// The calculate_all_width_by_height_secure function calculates the width of all items by a given height. The function checks that the width of the items does not exceed the available width, and that the width of the items does not exceed the minimum item width.
+    fn calculate_all_width_by_height_secure(
+        &self,
+        ratios: &[f64],
+        desired_height: f64,
+    ) -> Result<f64, ResizeError> {
+        if ratios.iter().any(|&ratio| {
+            self.is_too_narrow(ratio, desired_height)
+        }) {
+            return Err(ResizeError::MinItemWidthOverload);
+        }
+
+        let width = self.calculate_all_width_by_height(
+            ratios,
+            desired_height,
+        );
+
+        if !self.fits_width(width, ratios.iter().sum()) {
+            Err(ResizeError::CanNotFitItems)
+        } else {
+            Ok(width)
+        }
+    }
+
+    /// Check a row width against `available_width`, allowing for `epsilon`
+    /// and the overflow of rounded heights
+    fn fits_width(
+        &self,
+        width: f64,
+        ratio_sum: f64,
+    ) -> bool {
+        width
+            <= self.available_width
+                + self.epsilon
+                + self.rounding.max_overflow(ratio_sum)
+    }
+
+    /// Min width check of a single item, gaps are not part of its width
+    fn is_too_narrow(
+        &self,
+        ratio: f64,
+        height: f64,
+    ) -> bool {
+        height * ratio < self.min_item_width - self.epsilon
+    }
+
+    /// A row whose ratios add up to `max_ratio_sum_per_row` takes no more items
+    fn row_is_complete(&self, ratio_sum: f64) -> bool {
+        self.max_ratio_sum_per_row.is_some_and(|max| {
+            ratio_sum >= max - self.epsilon
+        })
+    }
+
+    /// Rows of several items may not be justified below `min_line_height`
+    fn is_too_short(&self, height: f64) -> bool {
+        height < self.min_line_height - self.epsilon
+    }
+
+    /// Whether a row justified at `height` has to break one item earlier
+    fn breaks_on_height(&self, height: f64) -> bool {
+        self.fit_policy == FitPolicy::ClampThenBreak
+            && self.is_too_short(height)
+    }
+
+    /// Check if we may fit all items with max or min line height
+    fn items_may_be_fitted(
+        &self,
+        ratios: &[f64],
+        height: f64,
+    ) -> bool {
+        self.calculate_all_width_by_height_secure(
+            ratios, height,
+        )
+        .is_ok()
+    }
+
+    /// Returns vector of tuples with number of items to take and height for them
+    // `ratios` stays a `&mut Vec` for existing callers
+    #[allow(clippy::ptr_arg)]
+    pub fn get_row_from_items(
+        &self,
+        ratios: &mut Vec<f64>,
+    ) -> Vec<(u32, f64)> {
+        self.get_row_from_items_with(
+            ratios,
+            &FillWidthSolver,
+        )
+    }
+
+    /// Same as `get_row_from_items`, row heights come from the given solver
+    pub fn get_row_from_items_with(
+        &self,
+        ratios: &[f64],
+        solver: &dyn HeightSolver,
+    ) -> Vec<(u32, f64)> {
+        let grid = self.layout_grid(ratios);
+        let mut rows =
+            grid.untruncated_rows(ratios, solver);
+        grid.truncate_rows(&mut rows);
+        rows
+    }
+
+    /// Grid rows are fitted with, gapless under `GapComputation::PostInset`
+    fn fit_grid(&self) -> Cow<'_, ImageGrid> {
+        match self.gap_mode {
+            GapComputation::Inline => Cow::Borrowed(self),
+            GapComputation::PostInset => {
+                Cow::Owned(ImageGrid {
+                    gap: 0.0,
+                    gap_mode: GapComputation::Inline,
+                    ..self.clone()
+                })
+            }
+        }
+    }
+
+    /// Grid the rows of `ratios` are laid out with, shared by
+    /// `get_row_from_items` and `rows_iter`: the `fit_grid`, scaled under
+    /// `OverflowPolicy::JustifyThenScale` when the rows exceed `max_rows`
+    /// and some scale fits them.
+    fn layout_grid(
+        &self,
+        ratios: &[f64],
+    ) -> Cow<'_, ImageGrid> {
+        let grid = self.fit_grid();
+
+        match (grid.overflow_policy, grid.max_rows) {
+            (
+                OverflowPolicy::JustifyThenScale,
+                Some(max_rows),
+            ) if grid.unbounded_rows(ratios).count()
+                > max_rows as usize =>
+            {
+                match grid.scaled_to_max_rows(ratios) {
+                    Some(scaled) => Cow::Owned(scaled),
+                    None => grid,
+                }
+            }
+            _ => grid,
+        }
+    }
+
+    /// Most item placements `layout_grid` spends on the scale search of
+    /// `OverflowPolicy::JustifyThenScale`: one count of the rows, then one
+    /// per bisection step
+    fn scale_search_ops(&self, ratios: &[f64]) -> u64 {
+        match (self.overflow_policy, self.max_rows) {
+            (OverflowPolicy::JustifyThenScale, Some(_)) => {
+                ratios.len() as u64
+                    * (Self::SCALE_SEARCH_STEPS as u64 + 1)
+            }
+            _ => 0,
+        }
+    }
+
+    /// Every row of `ratios`, ignoring `max_rows` and the overflow policy
+    fn unbounded_rows<'a>(
+        &'a self,
+        ratios: &'a [f64],
+    ) -> Rows<'a> {
+        Rows {
+            grid: Cow::Borrowed(self),
+            ratios,
+            prefix_sums: None,
+            start: 0,
+            max_rows: None,
+            previous: None,
+        }
+    }
+
+    /// `height` of the last row `row` once `last_row` is applied, which
+    /// happens when the row can't fill the width or falls below
+    /// `last_row_justify_threshold`. `previous` is the height of the row above.
+    fn last_row_height(
+        &self,
+        row: &[f64],
+        height: f64,
+        previous: Option<f64>,
+    ) -> f64 {
+        let sparse = previous.is_some_and(|previous| {
+            self.row_width_at_height(row, previous)
+                / self.available_width
+                < self.last_row_justify_threshold
+        });
+        let fill_height = self.get_optimal_height(row);
+        if !sparse
+            && fill_height
+                <= self.max_line_height + self.epsilon
+        {
+            return height;
+        }
+
+        let target = match (self.last_row, previous) {
+            (
+                LastRowBehavior::MatchPrevious,
+                Some(previous),
+            ) => previous,
+            (LastRowBehavior::Clamp(target), _) => target,
+            // A sparse row isn't stretched, it keeps the previous height
+            (LastRowBehavior::Justify, Some(previous))
+                if sparse =>
+            {
+                previous
+            }
+            _ => return height,
+        };
+        target.min(fill_height).min(self.max_line_height)
+    }
+
+    /// The grid with its height bounds and `min_item_width` scaled by the
+    /// highest factor fitting `max_rows`, searched by bisection. `None` if no
+    /// factor does.
+    fn scaled_to_max_rows(
+        &self,
+        ratios: &[f64],
+    ) -> Option<ImageGrid> {
+        let max_rows = self.max_rows? as usize;
+        let grid_at = |scale: f64| ImageGrid {
+            min_line_height: self.min_line_height * scale,
+            max_line_height: self.max_line_height * scale,
+            min_item_width: self.min_item_width * scale,
+            overflow_policy: OverflowPolicy::Truncate,
+            ..self.clone()
+        };
+        let mut low = 0.0;
+        let mut high = 1.0;
+        let mut best = None;
+
+        for _ in 0..Self::SCALE_SEARCH_STEPS {
+            let middle = (low + high) / 2.0;
+            let grid = grid_at(middle);
+            if grid.unbounded_rows(ratios).count()
+                <= max_rows
+            {
+                best = Some(grid);
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+
+        best
+    }
+
+    /// Drops the rows past `max_rows`
+    fn truncate_rows(&self, rows: &mut Vec<(u32, f64)>) {
+        if let Some(max_rows) = self.max_rows {
+            rows.truncate(max_rows as usize);
+        }
+    }
+
+    fn untruncated_rows(
+        &self,
+        ratios: &[f64],
+        solver: &dyn HeightSolver,
+    ) -> Vec<(u32, f64)> {
+        if ratios.len() < self.single_row_threshold as usize
+        {
+            let height = solver
+                .solve(ratios, self)
+                .min(self.max_line_height);
+            let height =
+                self.last_row_height(ratios, height, None);
+            return vec![(ratios.len() as u32, height)];
+        }
+
+        match self.justify_mode {
+            JustifyMode::Justify => {
+                self.split_rows(ratios, solver)
+            }
+            JustifyMode::None => {
+                self.unbounded_rows(ratios).collect()
+            }
+        }
+    }
+
+    /// Greedy rows one after the other, breaks come from `next_row` while
+    /// heights come from `solver`
+    fn split_rows(
+        &self,
+        ratios: &[f64],
+        solver: &dyn HeightSolver,
+    ) -> Vec<(u32, f64)> {
+        let mut rows: Vec<(u32, f64)> = Vec::new();
+        let mut start = 0;
+
+        while start < ratios.len() {
+            let (end, _) =
+                self.next_row(ratios, start, None);
+            let row = &ratios[start..end];
+            let mut height = solver
+                .solve(row, self)
+                .min(self.max_line_height);
+            if end == ratios.len() {
+                height = self.last_row_height(
+                    row,
+                    height,
+                    rows.last()
+                        .map(|&(_, previous)| previous),
+                );
+            }
+            rows.push(((end - start) as u32, height));
+            start = end;
+        }
+
+        rows
+    }
+
+    pub fn get_optimal_height(
+        &self,
+        ratios: &[f64],
+    ) -> f64 {
+        self.optimal_height_for(
+            ratios.len(),
+            ratios.iter().sum::<f64>(),
+        )
+    }
+
+    /// Optimal height for `count` items whose ratios add up to `ratio_sum`
+    fn optimal_height_for(
+        &self,
+        count: usize,
+        ratio_sum: f64,
+    ) -> f64 {
+        let gaps_width =
+            count.saturating_sub(1) as f64 * self.gap;
+        let total_width = self.available_width - gaps_width;
+        self.rounding.apply(total_width / ratio_sum)
+    }
+
+    /// Starts a session that lays out the same ratios at changing widths
+    pub fn resize_session<'a>(
+        &'a self,
+        ratios: &'a [f64],
+    ) -> ResizeSession<'a> {
+        ResizeSession::new(self, ratios)
+    }
+
+    /// Rows computed one at a time, same as `get_rows` without allocating
+    /// the whole result. Under `OverflowPolicy::JustifyThenScale` the scale is
+    /// searched up front once the rows exceed `max_rows`.
+    pub fn rows_iter<'a>(
+        &'a self,
+        ratios: &'a [f64],
+    ) -> Rows<'a> {
+        let grid = self.layout_grid(ratios);
+        let max_rows =
+            grid.max_rows.map(|max_rows| max_rows as usize);
+
+        Rows {
+            grid,
+            ratios,
+            prefix_sums: None,
+            start: 0,
+            max_rows,
+            previous: None,
+        }
+    }
+
+    /// End (exclusive) and height of the row starting at `start`. Ratio sums
+    /// come from `prefix_sums` when given, every candidate row is then
+    /// checked in O(1).
+    fn next_row(
+        &self,
+        ratios: &[f64],
+        start: usize,
+        prefix_sums: Option<&[f64]>,
+    ) -> (usize, f64) {
+        let limit = self.row_item_limit();
+        let mut end = start + 1;
+        let mut row_ratio_sum = ratios[start];
+
+        if self.justify_mode == JustifyMode::None {
+            let height = self.max_line_height;
+            let mut width = height * ratios[start];
+
+            while end < ratios.len()
+                && end - start < limit
+                && !self.row_is_complete(row_ratio_sum)
+                && width + self.gap + height * ratios[end]
+                    <= self.available_width + self.epsilon
+            {
+                width += self.gap + height * ratios[end];
+                row_ratio_sum += ratios[end];
+                end += 1;
+            }
+
+            let row = &ratios[start..end];
+            return (
+                end,
+                height.min(self.get_optimal_height(row)),
+            );
+        }
+
+        let mut min_ratio = ratios[start];
+        let mut height = self
+            .optimal_height_for(1, ratios[start])
+            .min(self.max_line_height);
+
+        while end < ratios.len()
+            && end - start < limit
+            && !self.row_is_complete(row_ratio_sum)
+        {
+            let count = end - start + 1;
+            let sum = match prefix_sums {
+                Some(prefix_sums) => {
+                    prefix_sums[end + 1]
+                        - prefix_sums[start]
+                }
+                None => row_ratio_sum + ratios[end],
+            };
+            let new_height =
+                self.optimal_height_for(count, sum);
+            let width = new_height * sum
+                + (count - 1) as f64 * self.gap;
+
+            if self.breaks_on_height(new_height)
+                || self.is_too_narrow(
+                    min_ratio.min(ratios[end]),
+                    new_height,
+                )
+                || !self.fits_width(width, sum)
+            {
+                break;
+            }
+
+            min_ratio = min_ratio.min(ratios[end]);
+            row_ratio_sum = sum;
+            height = new_height.min(self.max_line_height);
+            end += 1;
+        }
+
+        (end, height)
+    }
+
+    /// Pages of `page_rows` rows, returns the rows of page `page` and the
+    /// y-offset of its top edge. Rows after the page are never computed,
+    /// except by the scale search of `OverflowPolicy::JustifyThenScale` which
+    /// counts every row up front.
+    pub fn get_rows_page(
+        &self,
+        ratios: &[f64],
+        page: usize,
+        page_rows: usize,
+    ) -> (Vec<(u32, f64)>, f64) {
+        let mut rows = self.rows_iter(ratios);
+        let offset = rows
+            .by_ref()
+            .take(page * page_rows)
+            .map(|(_, height)| height + self.row_gap)
+            .sum();
+
+        (rows.take(page_rows).collect(), offset)
+    }
+
+    /// Same as `get_row_from_items`, but leaves the given ratios untouched
+    pub fn get_rows(
+        &self,
+        ratios: &[f64],
+    ) -> Vec<(u32, f64)> {
+        self.get_row_from_items(&mut ratios.to_vec())
+    }
+
+    /// Check if laying out the items at `new_width` would move any row break.
+    /// Only item counts are compared, height changes alone are not a reflow.
+    pub fn would_reflow(
+        &self,
+        ratios: &[f64],
+        current_rows: &[(u32, f64)],
+        new_width: f64,
+    ) -> bool {
+        let rows = self
+            .with_available_width(new_width)
+            .get_rows(ratios);
+
+        !rows.iter().map(|&(count, _)| count).eq(
+            current_rows.iter().map(|&(count, _)| count),
+        )
+    }
+
+    /// Builds a CSS grid stylesheet for the layout, scoped to
+    /// `CSS_GRID_CONTAINER`. See `to_css_grid_scoped` for the format and the
+    /// limits of the approximation.
+    pub fn to_css_grid(&self, ratios: &[f64]) -> String {
+        self.to_css_grid_scoped(
+            ratios,
+            Self::CSS_GRID_CONTAINER,
+        )
+    }
+
+    /// Builds a CSS grid stylesheet for the layout: a rule for `container`
+    /// with the columns, `grid-template-rows` holding every row height in px
+    /// and the gaps, then one `container > :nth-child` rule per item.
+    ///
+    /// CSS grid columns are discrete, so item widths are approximated by
+    /// spanning `CSS_GRID_COLUMNS` equal columns. Every item gets at least one
+    /// column and the columns of a row are handed out by largest remainder,
+    /// so a full row spans exactly `CSS_GRID_COLUMNS`. Aspect ratios are only
+    /// kept up to one column width, and a row with more items than columns
+    /// still wraps.
+    pub fn to_css_grid_scoped(
+        &self,
+        ratios: &[f64],
+        container: &str,
+    ) -> String {
+        use std::fmt::Write;
+
+        let rows = self.get_rows(ratios);
+        // A span of n columns also covers the n - 1 column gaps in it
+        let column_pitch = (self.available_width
+            + self.gap)
+            / Self::CSS_GRID_COLUMNS as f64;
+        let heights = rows
+            .iter()
+            .map(|(_, height)| format!("{}px", height))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let mut css = format!(
+            "{} {{\n  grid-template-columns: repeat({}, 1fr);\n  grid-template-rows: {};\n  column-gap: {}px;\n  row-gap: {}px;\n}}\n",
+            container,
+            Self::CSS_GRID_COLUMNS,
+            heights,
+            self.gap,
+            self.row_gap
+        );
+
+        let mut ratios = ratios.iter();
+        let mut item = 0;
+        for (row, &(count, height)) in
+            rows.iter().enumerate()
+        {
+            let exact = ratios
+                .by_ref()
+                .take(count as usize)
+                .map(|&ratio| {
+                    (height * ratio + self.gap)
+                        / column_pitch
+                })
+                .collect::<Vec<_>>();
+            for span in Self::css_spans(&exact) {
+                item += 1;
+                let _ = writeln!(
+                    css,
+                    "{} > :nth-child({}) {{ grid-row: {}; grid-column: span {}; }}",
+                    container,
+                    item,
+                    row + 1,
+                    span
+                );
+            }
+        }
+
+        css
+    }
+
+    /// Whole column spans for the exact spans of one row: every span is
+    /// floored to at least one column, then the columns left up to the
+    /// rounded row total (capped at `CSS_GRID_COLUMNS`) go to the largest
+    /// fractional remainders.
+    fn css_spans(exact: &[f64]) -> Vec<u32> {
+        let total = exact
+            .iter()
+            .sum::<f64>()
+            .round()
+            .min(Self::CSS_GRID_COLUMNS as f64)
+            as u32;
+        let mut spans = exact
+            .iter()
+            .map(|span| span.floor().max(1.0) as u32)
+            .collect::<Vec<_>>();
+        let mut order =
+            (0..exact.len()).collect::<Vec<_>>();
+        order.sort_by(|&a, &b| {
+            let rem =
+                |i: usize| exact[i] - exact[i].floor();
+            rem(b).total_cmp(&rem(a))
+        });
+        let used = spans.iter().sum::<u32>();
+        for &i in order
+            .iter()
+            .take(total.saturating_sub(used) as usize)
+        {
+            spans[i] += 1;
+        }
+        spans
+    }
+
+    /// Index of the first item of every row
+    pub fn break_indices(
+        &self,
+        ratios: &[f64],
+    ) -> Vec<usize> {
+        Self::row_starts(&self.get_rows(ratios)).collect()
+    }
+
+    fn row_starts(
+        rows: &[(u32, f64)],
+    ) -> impl Iterator<Item = usize> + '_ {
+        rows.iter().scan(0, |start, &(count, _)| {
+            let row_start = *start;
+            *start += count as usize;
+            Some(row_start)
+        })
+    }
+
+    /// Rows as borrowed sub-slices of `ratios`, paired with their height
+    pub fn row_slices<'a>(
+        &self,
+        ratios: &'a [f64],
+    ) -> Vec<(&'a [f64], f64)> {
+        let rows = self.get_rows(ratios);
+
+        Self::row_starts(&rows)
+            .zip(&rows)
+            .map(|(start, &(count, height))| {
+                (
+                    &ratios[start..start + count as usize],
+                    height,
+                )
+            })
+            .collect()
+    }
+
+    /// Position and size of every item, in the original order
+    pub fn get_positioned_layout(
+        &self,
+        ratios: &[f64],
+    ) -> Vec<PositionedItem> {
+        let items = ratios
+            .iter()
+            .copied()
+            .enumerate()
+            .collect::<Vec<_>>();
+        self.get_positioned_subset(&items)
+    }
+
+    /// Lays out only the given `(index, ratio)` pairs, e.g. a filtered view.
+    /// Geometry reflects the subset alone while `index` keeps the original one.
+    pub fn get_positioned_subset(
+        &self,
+        items: &[(usize, f64)],
+    ) -> Vec<PositionedItem> {
+        let ratios = items
+            .iter()
+            .map(|&(_, ratio)| ratio)
+            .collect::<Vec<_>>();
+        let mut items = items.iter();
+        let mut positioned =
+            Vec::with_capacity(ratios.len());
+        let mut y = 0.0;
+
+        for (count, height) in self.get_rows(&ratios) {
+            let content_width = self.available_width
+                - count.saturating_sub(1) as f64 * self.gap;
+            let mut x = 0.0;
+            let row = items.as_slice();
+            let row =
+                &row[..(count as usize).min(row.len())];
+            let scale = match self.gap_mode {
+                GapComputation::Inline => 1.0,
+                GapComputation::PostInset => {
+                    let natural = row
+                        .iter()
+                        .map(|&(_, ratio)| height * ratio)
+                        .sum::<f64>();
+                    (content_width / natural).min(1.0)
+                }
+            };
+            for &(index, ratio) in
+                items.by_ref().take(count as usize)
+            {
+                let width = height * ratio * scale;
+                positioned.push(PositionedItem {
+                    index,
+                    x: x + self.inset,
+                    y: y + self.inset,
+                    width: width - 2.0 * self.inset,
+                    height: height - 2.0 * self.inset,
+                    percent_width: width / content_width
+                        * 100.0,
+                });
+                x += width + self.gap;
+            }
+            y += height + self.row_gap;
+        }
+
+        positioned
+    }
+
+    /// Share of `available_width` left empty by the last row, `(width, height)`
+    /// per item. Zero means a fully justified bottom edge.
+    pub fn bottom_raggedness(
+        &self,
+        rows_with_widths: &[Vec<(f64, f64)>],
+    ) -> f64 {
+        let Some(last_row) = rows_with_widths.last() else {
+            return 0.0;
+        };
+        let used = last_row
+            .iter()
+            .fold(-self.gap, |acc, &(width, _)| {
+                acc + width + self.gap
+            });
+
+        (self.available_width - used.max(0.0)).max(0.0)
+            / self.available_width
+    }
+
+    /// Theoretical max amount of items in a single row, every item being
+    /// exactly `min_item_width` wide
+    pub fn max_items_in_row(&self) -> u32 {
+        ((self.available_width + self.gap)
+            / (self.min_item_width + self.gap))
+            .floor() as u32
+    }
+
+    /// Same as `get_positioned_layout`, stored as a struct of arrays
+    pub fn get_positioned_soa(
+        &self,
+        ratios: &[f64],
+    ) -> LayoutSoA {
+        let layout = self.get_positioned_layout(ratios);
+        let mut soa = LayoutSoA {
+            xs: Vec::with_capacity(layout.len()),
+            ys: Vec::with_capacity(layout.len()),
+            widths: Vec::with_capacity(layout.len()),
+            heights: Vec::with_capacity(layout.len()),
+        };
+
+        for item in layout {
+            soa.xs.push(item.x);
+            soa.ys.push(item.y);
+            soa.widths.push(item.width);
+            soa.heights.push(item.height);
+        }
+
+        soa
+    }
+
+    /// Layout diagnostics: rows with their slack and clamp state, totals and
+    /// every constraint the layout had to break. `nan_policy` is applied
+    /// first, input refused by it is reported without rows.
+    pub fn layout_report(
+        &self,
+        ratios: &[f64],
+    ) -> LayoutReport {
+        let (ratios, skipped) =
+            match self.apply_nan_policy(ratios) {
+                Ok(cleaned) => cleaned,
+                Err(error) => {
+                    return LayoutReport {
+                        rows: Vec::new(),
+                        stats: LayoutStats::default(),
+                        errors: vec![ConstraintViolation {
+                            row: None,
+                            error,
+                        }],
+                        skipped: Vec::new(),
+                    }
+                }
+            };
+        let ratios = ratios.as_slice();
+        let mut errors = Vec::new();
+        if ratios.is_empty() {
+            errors.push(ConstraintViolation {
+                row: None,
+                error: ResizeError::Empty,
+            });
+        }
+
+        // Rows are checked against the grid they were fitted with
+        let grid = self.layout_grid(ratios);
+        let rows = grid
+            .row_slices(ratios)
+            .into_iter()
+            .filter(|(row, _)| !row.is_empty())
+            .enumerate()
+            .map(|(index, (row, height))| {
+                let width =
+                    grid.calculate_all_width_by_height(row, height);
+                if let Err(error) = grid
+                    .calculate_all_width_by_height_secure(row, height)
+                {
+                    errors.push(ConstraintViolation {
+                        row: Some(index),
+                        error,
+                    });
+                }
+                if height > grid.max_line_height + grid.epsilon {
+                    errors.push(ConstraintViolation {
+                        row: Some(index),
+                        error: ResizeError::BiggerThanMaxHeight,
+                    });
+                }
+                if grid.is_too_short(height) {
+                    errors.push(ConstraintViolation {
+                        row: Some(index),
+                        error: ResizeError::LowerThanMinHeight,
+                    });
+                }
+
+                RowReport {
+                    count: row.len() as u32,
+                    height,
+                    slack: grid.available_width - width,
+                    clamped: height < grid.get_optimal_height(row),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let stats = LayoutStats {
+            row_count: rows.len(),
+            item_count: ratios.len(),
+            total_height: rows
+                .iter()
+                .map(|row| row.height)
+                .sum::<f64>()
+                + rows.len().saturating_sub(1) as f64
+                    * grid.row_gap,
+        };
+
+        LayoutReport { rows, stats, errors, skipped }
+    }
+
+    /// Lays out the items while respecting `max_rows` by dropping the lowest
+    /// priority items first (the latest one on ties) instead of the last rows.
+    /// Items without a priority count as `0`. This only changes which items
+    /// survive, the survivors keep their original order.
+    ///
+    /// Returns the rows and the original indices of the items they hold.
+    pub fn get_rows_with_priorities(
+        &self,
+        ratios: &[f64],
+        priorities: &[u32],
+    ) -> (Vec<(u32, f64)>, Vec<usize>) {
+        let priority = |index: usize| {
+            priorities.get(index).copied().unwrap_or(0)
+        };
+
+        self.rows_dropping_items(ratios, None, |kept| {
+            kept.iter()
+                .enumerate()
+                .min_by_key(|&(_, &index)| {
+                    (
+                        priority(index),
+                        std::cmp::Reverse(index),
+                    )
+                })
+                .map(|(position, _)| position)
+        })
+    }
+
+    /// Same as `get_rows_with_priorities`, but only the latest items outside
+    /// `pinned` get dropped, to respect `max_rows` as well as `max_items`.
+    /// Pinned items always survive, even if that leaves too many rows.
+    pub fn get_rows_with_pinned(
+        &self,
+        ratios: &[f64],
+        pinned: &[usize],
+        max_items: Option<usize>,
+    ) -> (Vec<(u32, f64)>, Vec<usize>) {
+        self.rows_dropping_items(
+            ratios,
+            max_items,
+            |kept| {
+                kept.iter().rposition(|index| {
+                    !pinned.contains(index)
+                })
+            },
+        )
+    }
+
+    /// Drops the item at the position picked by `next_drop` among the kept
+    /// ones until the rows fit `max_rows` and `max_items`, or nothing may be
+    /// dropped anymore
+    fn rows_dropping_items(
+        &self,
+        ratios: &[f64],
+        max_items: Option<usize>,
+        next_drop: impl Fn(&[usize]) -> Option<usize>,
+    ) -> (Vec<(u32, f64)>, Vec<usize>) {
+        let mut kept =
+            (0..ratios.len()).collect::<Vec<_>>();
+
+        loop {
+            let kept_ratios = kept
+                .iter()
+                .map(|&index| ratios[index])
+                .collect::<Vec<_>>();
+            let grid = self.layout_grid(&kept_ratios);
+            let rows = grid.untruncated_rows(
+                &kept_ratios,
+                &FillWidthSolver,
+            );
+            let fits =
+                grid.max_rows.is_none_or(|max_rows| {
+                    rows.len() <= max_rows as usize
+                }) && max_items.is_none_or(|max_items| {
+                    kept.len() <= max_items
+                });
+
+            match next_drop(&kept) {
+                Some(position) if !fits => {
+                    kept.remove(position);
+                }
+                _ => return (rows, kept),
+            }
+        }
+    }
+
+    /// Searches `search_range` for the first width where the last row fills
+    /// the width as well, i.e. it isn't capped at `max_line_height` and only
+    /// height rounding leaves slack. Widths are tried in 1px steps, or in
+    /// `FULL_LAST_ROW_SEARCH_STEPS` even steps over wider ranges. `None` if
+    /// no width qualifies or the range isn't finite.
+    pub fn width_for_full_last_row(
+        &self,
+        ratios: &[f64],
+        search_range: (f64, f64),
+    ) -> Option<f64> {
+        let (from, to) = search_range;
+        if ratios.is_empty()
+            || !from.is_finite()
+            || !to.is_finite()
+            || to < from
+        {
+            return None;
+        }
+
+        let steps = ((to - from) as u64)
+            .min(Self::FULL_LAST_ROW_SEARCH_STEPS);
+        let step_width = if steps == 0 {
+            0.0
+        } else {
+            ((to - from) / steps as f64).max(1.0)
+        };
+
+        (0..=steps)
+            .map(|step| from + step as f64 * step_width)
+            .find(|&width| {
+                let grid = self.with_available_width(width);
+                let rows = grid.row_slices(ratios);
+                rows.last().is_some_and(|&(row, height)| {
+                    height
+                        >= grid.get_optimal_height(row)
+                            - grid.epsilon
+                })
+            })
+    }
+
+    /// `(item_index, old_height, new_height)` of every item whose height
+    /// differs between the layouts at `width_a` and `width_b`. Items dropped
+    /// from either layout by `max_rows` are left out.
+    pub fn height_deltas(
+        &self,
+        ratios: &[f64],
+        width_a: f64,
+        width_b: f64,
+    ) -> Vec<(usize, f64, f64)> {
+        let item_heights = |width: f64| {
+            self.with_available_width(width)
+                .get_rows(ratios)
+                .into_iter()
+                .flat_map(|(count, height)| {
+                    std::iter::repeat_n(
+                        height,
+                        count as usize,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+
+        item_heights(width_a)
+            .into_iter()
+            .zip(item_heights(width_b))
+            .enumerate()
+            .filter(|&(_, (old, new))| old != new)
+            .map(|(index, (old, new))| (index, old, new))
+            .collect()
+    }
+
+    /// `max_line_height` giving a total grid height closest to
+    /// `available_width / target_aspect`, e.g. `16.0 / 9.0` for a 16:9 card.
+    /// Searched in whole pixels from `min_line_height` up to the point where
+    /// raising it no longer changes the layout, at most `1e6`.
+    pub fn fit_grid_aspect(
+        &self,
+        ratios: &[f64],
+        target_aspect: f64,
+    ) -> f64 {
+        self.max_line_height_for_total(
+            ratios,
+            self.available_width / target_aspect,
+        )
+    }
+
+    /// `max_line_height` giving a total grid height closest to `target`, see
+    /// `fit_grid_aspect`
+    fn max_line_height_for_total(
+        &self,
+        ratios: &[f64],
+        target: f64,
+    ) -> f64 {
+        let total = |max_line_height: f64| {
+            let grid =
+                self.with_max_line_height(max_line_height);
+            grid.rows_total_height(&grid.get_rows(ratios))
+        };
+        // Items without a positive ratio never widen with the height
+        let narrowest = ratios
+            .iter()
+            .copied()
+            .filter(|&ratio| ratio > 0.0)
+            .fold(f64::INFINITY, f64::min);
+        let mut low = self.min_line_height.ceil();
+        let mut high = (self.available_width / narrowest)
+            .ceil()
+            .min(Self::LINE_HEIGHT_SEARCH_LIMIT)
+            .max(low);
+
+        // Total height only grows with max_line_height, look for the first
+        // height reaching the target
+        while low < high {
+            let middle = ((low + high) / 2.0).floor();
+            if total(middle) < target {
+                low = middle + 1.0;
+            } else {
+                high = middle;
+            }
+        }
+
+        let below =
+            (low - 1.0).max(self.min_line_height.ceil());
+        if (total(below) - target).abs()
+            <= (total(low) - target).abs()
+        {
+            below
+        } else {
+            low
+        }
+    }
+
+    /// Justified heights for caller chosen rows, `breaks` holds the index of
+    /// the first item of every row (so it starts with `0`). Errors when the
+    /// breaks are invalid or a row violates the width or height constraints.
+    pub fn heights_for_breaks(
+        &self,
+        ratios: &[f64],
+        breaks: &[usize],
+    ) -> Result<Vec<f64>, ResizeError> {
+        if ratios.is_empty() {
+            return Err(ResizeError::Empty);
+        }
+        let valid = breaks.first() == Some(&0)
+            && breaks
+                .windows(2)
+                .all(|pair| pair[0] < pair[1])
+            && breaks.last() < Some(&ratios.len());
+        if !valid {
+            return Err(ResizeError::InvalidBreaks);
+        }
+
+        breaks
+            .iter()
+            .zip(
+                breaks
+                    .iter()
+                    .skip(1)
+                    .chain([&ratios.len()]),
+            )
+            .map(|(&start, &end)| {
+                self.justified_row_height(
+                    &ratios[start..end],
+                )
+            })
+            .collect()
+    }
+
+    /// Height of the given items justified as a single row, errors when the
+    /// row violates the width or height constraints
+    fn justified_row_height(
+        &self,
+        row: &[f64],
+    ) -> Result<f64, ResizeError> {
+        let height = self
+            .get_optimal_height(row)
+            .min(self.max_line_height);
+        if self.is_too_short(height) {
+            return Err(ResizeError::LowerThanMinHeight);
+        }
+        self.calculate_all_width_by_height_secure(
+            row, height,
+        )
+        .map(|_| height)
+    }
+
+    /// Lays out every group on its own, groups are stacked in order of first
+    /// appearance with `section_gap` between them. Items keep their index in
+    /// `items` and are positioned relative to their group, whose y-offset is
+    /// returned alongside.
+    pub fn get_grouped_layout<K: Eq + Hash + Clone>(
+        &self,
+        items: &[(K, f64)],
+        section_gap: f64,
+    ) -> Vec<(K, Vec<PositionedItem>, f64)> {
+        let mut group_indices: HashMap<K, usize> =
+            HashMap::new();
+        let mut groups: Vec<(K, Vec<(usize, f64)>)> =
+            Vec::new();
+
+        for (index, (key, ratio)) in
+            items.iter().enumerate()
+        {
+            let group = *group_indices
+                .entry(key.clone())
+                .or_insert_with(|| {
+                    groups.push((key.clone(), Vec::new()));
+                    groups.len() - 1
+                });
+            groups[group].1.push((index, *ratio));
+        }
+
+        let mut y_offset = 0.0;
+        groups
+            .into_iter()
+            .map(|(key, group_items)| {
+                let layout = self
+                    .get_positioned_subset(&group_items);
+                let offset = y_offset;
+                let ratios = group_items
+                    .iter()
+                    .map(|&(_, ratio)| ratio)
+                    .collect::<Vec<_>>();
+                let height = self.rows_total_height(
+                    &self.get_rows(&ratios),
+                );
+                y_offset += height + section_gap;
+                (key, layout, offset)
+            })
+            .collect()
+    }
+
+    /// Cost of a single row in the optimal partitioner: the squared distance
+    /// of its height to the middle of `min_line_height..max_line_height`.
+    /// A last row taller than the middle is free, infeasible rows cost
+    /// `f64::INFINITY`.
+    fn row_cost(&self, row: &[f64], is_last: bool) -> f64 {
+        let optimal = self.get_optimal_height(row);
+        let feasible = row.len() <= self.row_item_limit()
+            && (row.len() == 1
+                || !self.is_too_short(optimal))
+            && (row.len() == 1
+                || self.items_may_be_fitted(row, optimal));
+        if !feasible {
+            return f64::INFINITY;
+        }
+
+        let target = (self.min_line_height
+            + self.max_line_height)
+            / 2.0;
+        let height = optimal.min(self.max_line_height);
+        if is_last && height >= target {
+            0.0
+        } else {
+            (height - target).powi(2)
+        }
+    }
+
+    /// Total cost of the given rows under the optimal partitioner's cost function
+    pub fn layout_cost(
+        &self,
+        ratios: &[f64],
+        rows: &[(u32, f64)],
+    ) -> f64 {
+        let grid = self.fit_grid();
+        let mut start = 0;
+        rows.iter()
+            .enumerate()
+            .map(|(index, &(count, _))| {
+                let end = (start + count as usize)
+                    .min(ratios.len());
+                let cost = grid.row_cost(
+                    &ratios[start..end],
+                    index + 1 == rows.len(),
+                );
+                start = end;
+                cost
+            })
+            .sum()
+    }
+
+    /// Picks the row breaks minimizing the total `layout_cost` over the whole
+    /// gallery instead of filling rows greedily. On equal costs the shorter
+    /// last row wins, unless a `seed` is set.
+    pub fn get_rows_optimal(
+        &self,
+        ratios: &[f64],
+    ) -> Vec<(u32, f64)> {
+        self.get_rows_optimal_with_cost(ratios).0
+    }
+
+    /// Same as `get_rows_optimal`, along with the minimized cost
+    pub fn get_rows_optimal_with_cost(
+        &self,
+        ratios: &[f64],
+    ) -> (Vec<(u32, f64)>, f64) {
+        let grid = self.fit_grid();
+        if ratios.is_empty() {
+            return (Vec::new(), 0.0);
+        }
+        if ratios.len() < grid.single_row_threshold as usize
+        {
+            let rows = grid.get_rows(ratios);
+            let cost = grid.layout_cost(ratios, &rows);
+            return (rows, cost);
+        }
+
+        // best[end] is the cheapest way to lay out ratios[..end], along with
+        // the start of its last row
+        let mut best =
+            vec![(f64::INFINITY, 0); ratios.len() + 1];
+        best[0] = (0.0, 0);
+        let mut rng = grid.seed.map(SplitMix64);
+
+        for end in 1..=ratios.len() {
+            for start in (0..end).rev() {
+                let cost = grid.row_cost(
+                    &ratios[start..end],
+                    end == ratios.len(),
+                );
+                if cost.is_infinite() && end - start > 1 {
+                    // Longer rows only get shorter and narrower
+                    break;
+                }
+                let total = best[start].0 + cost;
+                let wins_tie = total == best[end].0
+                    && total.is_finite()
+                    && rng.as_mut().is_some_and(|rng| {
+                        rng.next_u64() & 1 == 1
+                    });
+                if total < best[end].0 || wins_tie {
+                    best[end] = (total, start);
+                }
+            }
+        }
+
+        let mut rows = Vec::new();
+        let mut end = ratios.len();
+        while end > 0 {
+            let start = best[end].1;
+            let row = &ratios[start..end];
+            rows.push((
+                row.len() as u32,
+                grid.get_optimal_height(row)
+                    .min(grid.max_line_height),
+            ));
+            end = start;
+        }
+        rows.reverse();
+
+        let cost = best[ratios.len()].0;
+        grid.truncate_rows(&mut rows);
+        (rows, cost)
+    }
+
+    /// Row indices (in the updated layout) that differ once `ratios[index]`
+    /// becomes `new_ratio`, rows before the changed item's row never do.
+    /// Rows that end up identical again after the change are not included.
+    pub fn rows_affected_by_ratio_change(
+        &self,
+        ratios: &[f64],
+        index: usize,
+        new_ratio: f64,
+    ) -> std::ops::Range<usize> {
+        let before = self.get_rows(ratios);
+        if index >= ratios.len() {
+            return before.len()..before.len();
+        }
+
+        let mut changed = ratios.to_vec();
+        changed[index] = new_ratio;
+        let after = self.get_rows(&changed);
+
+        let start = before
+            .iter()
+            .zip(&after)
+            .take_while(|(old, new)| old == new)
+            .count();
+        let common_tail = before
+            .iter()
+            .rev()
+            .zip(after.iter().rev())
+            .take_while(|(old, new)| old == new)
+            .count()
+            .min(after.len() - start);
+
+        start..after.len() - common_tail
+    }
+
+    /// Index of the item under the given point, gaps between items hit nothing
+    pub fn hit_test(
+        &self,
+        items: &[PositionedItem],
+        px: f64,
+        py: f64,
+    ) -> Option<usize> {
+        items
+            .iter()
+            .find(|item| item.contains(px, py))
+            .map(|item| item.index)
+    }
+
+    /// Pulls a lone item of the last row into the previous one when, at the
+    /// previous row's height, the merged row stays within
+    /// `available_width * (1 + tolerance)`, the row item limit and, once
+    /// justified, the height bounds and `min_item_width`. Other layouts are
+    /// returned as is.
+    pub fn absorb_single_orphan(
+        &self,
+        ratios: &[f64],
+        mut rows: Vec<(u32, f64)>,
+        tolerance: f64,
+    ) -> Vec<(u32, f64)> {
+        let [.., (previous_count, previous_height), (1, _)] =
+            rows[..]
+        else {
+            return rows;
+        };
+        let placed = Self::placed_item_count(&rows);
+        if placed > ratios.len() {
+            return rows;
+        }
+
+        let merged = &ratios
+            [placed - 1 - previous_count as usize..placed];
+        if merged.len() > self.row_item_limit() {
+            return rows;
+        }
+        let width = self.calculate_all_width_by_height(
+            merged,
+            previous_height,
+        );
+        if width
+            > self.available_width * (1.0 + tolerance)
+                + self.epsilon
+        {
+            return rows;
+        }
+
+        if let Ok(height) =
+            self.justified_row_height(merged)
+        {
+            rows.pop();
+            if let Some(last) = rows.last_mut() {
+                *last = (previous_count + 1, height);
+            }
+        }
+
+        rows
+    }
+
+    /// Ratios cleaned up according to `nan_policy`, along with the indices
+    /// of the items dropped by `NanPolicy::Skip`
+    pub fn apply_nan_policy(
+        &self,
+        ratios: &[f64],
+    ) -> Result<(Vec<f64>, Vec<usize>), ResizeError> {
+        let mut cleaned = Vec::with_capacity(ratios.len());
+        let mut skipped = Vec::new();
+
+        for (index, &ratio) in ratios.iter().enumerate() {
+            if !ratio.is_nan() {
+                cleaned.push(ratio);
+                continue;
+            }
+            match self.nan_policy {
+                NanPolicy::Error => {
+                    return Err(ResizeError::NanRatio)
+                }
+                NanPolicy::Skip => skipped.push(index),
+                NanPolicy::Substitute(fallback) => {
+                    cleaned.push(fallback)
+                }
+            }
+        }
+
+        Ok((cleaned, skipped))
+    }
+
+    /// Same as `get_rows` once `nan_policy` is applied, along with the
+    /// indices of skipped items. Errors with `Empty` when no item is left.
+    pub fn get_rows_checked(
+        &self,
+        ratios: &[f64],
+    ) -> Result<CheckedRows, ResizeError> {
+        let (ratios, skipped) =
+            self.apply_nan_policy(ratios)?;
+        if ratios.is_empty() {
+            return Err(ResizeError::Empty);
+        }
+        Ok((self.get_rows(&ratios), skipped))
+    }
+
+    /// Share of each item in its row content width (gaps excluded), so every
+    /// row sums to 1.0. Handy to emit `fr` units.
+    pub fn row_fractions(
+        &self,
+        ratios: &[f64],
+    ) -> Vec<Vec<f64>> {
+        self.row_slices(ratios)
+            .into_iter()
+            .map(|(row, height)| {
+                let widths = row
+                    .iter()
+                    .map(|ratio| ratio * height)
+                    .collect::<Vec<_>>();
+                let total = widths.iter().sum::<f64>();
+                widths
+                    .iter()
+                    .map(|width| width / total)
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Best effort layout bounded by `max_ops` item placements, returns the
+    /// rows done so far and whether the layout went through. The budget is
+    /// checked between rows, so the last row may exceed it. The scale search
+    /// of `OverflowPolicy::JustifyThenScale` is charged up front, nothing is
+    /// laid out when it alone doesn't fit the budget.
+    pub fn get_rows_timed(
+        &self,
+        ratios: &[f64],
+        max_ops: u64,
+    ) -> (Vec<(u32, f64)>, bool) {
+        let search_ops = self.scale_search_ops(ratios);
+        if search_ops > 0 && search_ops >= max_ops {
+            return (Vec::new(), false);
+        }
+
+        let mut rows = self.rows_iter(ratios);
+        let mut done = Vec::new();
+        let mut ops = search_ops;
+
+        while ops < max_ops {
+            let Some((count, height)) = rows.next() else {
+                return (done, true);
+            };
+            ops += count as u64;
+            done.push((count, height));
+        }
+
+        (done, rows.is_done())
+    }
+
+    /// Rendered area over the area every item would take at
+    /// `max_line_height`. Below 1 means rows were shrunk to fit.
+    pub fn compression_ratio(&self, ratios: &[f64]) -> f64 {
+        let ideal = ratios
+            .iter()
+            .map(|ratio| {
+                ratio * self.max_line_height.powi(2)
+            })
+            .sum::<f64>();
+        if ideal == 0.0 {
+            return 1.0;
+        }
+
+        let rendered = self
+            .row_slices(ratios)
+            .into_iter()
+            .map(|(row, height)| {
+                row.iter().sum::<f64>() * height.powi(2)
+            })
+            .sum::<f64>();
+
+        rendered / ideal
+    }
+
+    /// Items fully shown above `fold_height`
+    fn visible_above(
+        &self,
+        ratios: &[f64],
+        fold_height: f64,
+    ) -> u32 {
+        let mut bottom = 0.0;
+
+        self.rows_iter(ratios)
+            .take_while(|&(_, height)| {
+                bottom += height;
+                let visible =
+                    bottom <= fold_height + self.epsilon;
+                bottom += self.row_gap;
+                visible
+            })
+            .map(|(count, _)| count)
+            .sum()
+    }
+
+    /// Highest `max_line_height`, in whole pixels and never above the current
+    /// one, fitting at least `min_visible` items above `fold_height`. Falls
+    /// back to `min_line_height` when no height does.
+    pub fn max_height_for_min_visible(
+        &self,
+        ratios: &[f64],
+        fold_height: f64,
+        min_visible: u32,
+    ) -> f64 {
+        let fits = |max_line_height: f64| {
+            self.with_max_line_height(max_line_height)
+                .visible_above(ratios, fold_height)
+                >= min_visible
+        };
+        if fits(self.max_line_height) {
+            return self.max_line_height;
+        }
+
+        let mut low = self.min_line_height.ceil();
+        let mut high =
+            (self.max_line_height.floor() - 1.0).max(low);
+        if !fits(low) {
+            return self.min_line_height;
+        }
+
+        // Fewer items fit as max_line_height grows, look for the last height
+        // still fitting enough of them
+        while low < high {
+            let middle = ((low + high) / 2.0).ceil();
+            if fits(middle) {
+                low = middle;
+            } else {
+                high = middle - 1.0;
+            }
+        }
+
+        low
+    }
+
+    /// y-offset of the top edge of every row
+    pub fn row_offsets(&self, ratios: &[f64]) -> Vec<f64> {
+        self.rows_iter(ratios)
+            .scan(0.0, |top, (_, height)| {
+                let row_top = *top;
+                *top += height + self.row_gap;
+                Some(row_top)
+            })
+            .collect()
+    }
+
+    /// y-offset and height of rows `start_row..=end_row` taken as a block,
+    /// gaps between them included. `None` when the range is empty or out of
+    /// bounds.
+    pub fn row_range_bounds(
+        &self,
+        ratios: &[f64],
+        start_row: usize,
+        end_row: usize,
+    ) -> Option<(f64, f64)> {
+        let rows = self.get_rows(ratios);
+        let block = rows
+            .get(start_row..=end_row)
+            .filter(|block| !block.is_empty())?;
+        let top = rows[..start_row]
+            .iter()
+            .map(|&(_, height)| height + self.row_gap)
+            .sum();
+
+        Some((top, self.rows_total_height(block)))
+    }
+
+    /// Packs as many items per row as `min_line_height` allows. Unlike
+    /// `get_row_from_items`, which also breaks as soon as an item would get
+    /// narrower than `min_item_width`, only the row height bounds a row here.
+    pub fn get_rows_densest(
+        &self,
+        ratios: &[f64],
+    ) -> Vec<(u32, f64)> {
+        let grid = self.fit_grid();
+        let limit = grid.row_item_limit();
+        let mut rows = Vec::new();
+        let mut start = 0;
+
+        while start < ratios.len() {
+            let mut end = start + 1;
+            let mut ratio_sum = ratios[start];
+
+            while end < ratios.len() && end - start < limit
+            {
+                let sum = ratio_sum + ratios[end];
+                let height = grid.optimal_height_for(
+                    end - start + 1,
+                    sum,
+                );
+                if grid.is_too_short(height) {
+                    break;
+                }
+                ratio_sum = sum;
+                end += 1;
+            }
+
+            let height = grid
+                .optimal_height_for(end - start, ratio_sum)
+                .min(grid.max_line_height);
+            rows.push(((end - start) as u32, height));
+            start = end;
+        }
+
+        grid.truncate_rows(&mut rows);
+        rows
+    }
+
+    /// Per row, how far the chosen height is from the natural height that
+    /// fills the width, as `height / fill_height - 1`. Justified rows report
+    /// ~0, rows held below their fill height (clamped to `max_line_height`
+    /// or by the `last_row` policy) a negative value.
+    pub fn row_tension(&self, ratios: &[f64]) -> Vec<f64> {
+        self.row_slices(ratios)
+            .into_iter()
+            .map(|(row, height)| {
+                let fill_height = self.optimal_height_for(
+                    row.len(),
+                    row.iter().sum(),
+                );
+                height / fill_height - 1.0
+            })
+            .collect()
+    }
+
+    /// Rows grouped by page of `page_height`, a row that doesn't fully fit
+    /// moves to the next page. Rows taller than a page get one of their own.
+    pub fn paginate(
+        &self,
+        ratios: &[f64],
+        page_height: f64,
+    ) -> Vec<Vec<(u32, f64)>> {
+        let mut pages: Vec<Vec<(u32, f64)>> = Vec::new();
+        let mut used = 0.0;
+
+        for (count, height) in self.rows_iter(ratios) {
+            match pages.last_mut() {
+                Some(page)
+                    if used + self.row_gap + height
+                        <= page_height + self.epsilon =>
+                {
+                    used += self.row_gap + height;
+                    page.push((count, height));
+                }
+                _ => {
+                    used = height;
+                    pages.push(vec![(count, height)]);
+                }
+            }
+        }
+
+        pages
+    }
+
+    /// `max_line_height` and rows giving a total height closest to
+    /// `target_total`, searched the same way as `fit_grid_aspect`
+    pub fn best_fit_total_height(
+        &self,
+        ratios: &[f64],
+        target_total: f64,
+    ) -> (f64, Vec<(u32, f64)>) {
+        let max_line_height = self
+            .max_line_height_for_total(
+                ratios,
+                target_total,
+            );
+        let rows = self
+            .with_max_line_height(max_line_height)
+            .get_rows(ratios);
+
+        (max_line_height, rows)
+    }
+
+    /// Moves the break between the last two rows to where their heights are
+    /// the closest, among the splits keeping both rows within the constraints
+    /// and the row item limit. On ties the longer second to last row wins.
+    /// Other layouts are returned as is.
+    pub fn balance_tail(
+        &self,
+        ratios: &[f64],
+        mut rows: Vec<(u32, f64)>,
+    ) -> Vec<(u32, f64)> {
+        let [.., (first_count, first_height), (last_count, last_height)] =
+            rows[..]
+        else {
+            return rows;
+        };
+        let placed = Self::placed_item_count(&rows);
+        if placed > ratios.len() {
+            return rows;
+        }
+
+        let tail_len = (first_count + last_count) as usize;
+        let tail = &ratios[placed - tail_len..placed];
+        let mut best = (
+            (first_height - last_height).abs(),
+            (first_count, first_height),
+            (last_count, last_height),
+        );
+
+        let limit = self.row_item_limit();
+
+        for split in (1..tail_len).rev() {
+            if split > limit || tail_len - split > limit {
+                continue;
+            }
+            let (first, last) = tail.split_at(split);
+            let (Ok(first_height), Ok(last_height)) = (
+                self.justified_row_height(first),
+                self.justified_row_height(last),
+            ) else {
+                continue;
+            };
+            let difference =
+                (first_height - last_height).abs();
+            if difference < best.0 {
+                best = (
+                    difference,
+                    (split as u32, first_height),
+                    (
+                        (tail_len - split) as u32,
+                        last_height,
+                    ),
+                );
+            }
+        }
+
+        let rows_len = rows.len();
+        rows[rows_len - 2] = best.1;
+        rows[rows_len - 1] = best.2;
+        rows
+    }
+
+    /// Sorted unique row heights, heights within `epsilon` count as one
+    pub fn distinct_row_heights(
+        &self,
+        ratios: &[f64],
+    ) -> Vec<f64> {
+        let mut heights = self
+            .rows_iter(ratios)
+            .map(|(_, height)| height)
+            .collect::<Vec<_>>();
+        heights.sort_by(f64::total_cmp);
+        heights.dedup_by(|height, previous| {
+            (*height - *previous).abs() <= self.epsilon
+        });
+        heights
+    }
+
+    /// `(flex_grow, flex_basis)` of every item per row, for a flexbox gallery
+    /// where the browser justifies the rows. Grow is the ratio and basis the
+    /// width at `min_line_height`.
+    pub fn flex_values(
+        &self,
+        ratios: &[f64],
+    ) -> Vec<Vec<(f64, f64)>> {
+        self.row_slices(ratios)
+            .into_iter()
+            .map(|(row, _)| {
+                row.iter()
+                    .map(|&ratio| {
+                        (
+                            ratio,
+                            ratio * self.min_line_height,
+                        )
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Smallest gap keeping the widest item at or below `max_item_width` when
+    /// `ratios` are justified as a single full row. `0` when no gap is needed
+    /// or a single item leaves no gap to grow.
+    pub fn gap_for_max_item_width(
+        &self,
+        ratios: &[f64],
+        max_item_width: f64,
+    ) -> f64 {
+        if ratios.len() < 2 {
+            return 0.0;
+        }
+        let ratio_sum = ratios.iter().sum::<f64>();
+        let widest =
+            ratios.iter().copied().fold(0.0, f64::max);
+        let max_height = max_item_width / widest;
+        let gaps = (ratios.len() - 1) as f64;
+
+        ((self.available_width - max_height * ratio_sum)
+            / gaps)
+            .max(0.0)
+    }
+
+    /// Rows as text for snapshot diffs, one `<count> x <height>` line per row
+    /// with heights always printed with `SNAPSHOT_DECIMALS` decimals
+    pub fn to_snapshot_string(
+        &self,
+        ratios: &[f64],
+    ) -> String {
+        self.rows_iter(ratios)
+            .map(|(count, height)| {
+                format!(
+                    "{count} x {height:.decimals$}\n",
+                    decimals = Self::SNAPSHOT_DECIMALS
+                )
+            })
+            .collect()
+    }
+
+    /// Rendered height of the whole grid, rows of `get_row_from_items`
+    /// stacked with `row_gap` between them like in `get_positioned_layout`
+    pub fn total_height(
+        &self,
+        ratios: &mut Vec<f64>,
+    ) -> f64 {
+        self.rows_total_height(
+            &self.get_row_from_items(ratios),
+        )
+    }
+
+    /// Original index of the first item of the first row reaching below `y`,
+    /// rows hidden in the `row_gap` above `y` don't count. `None` past the
+    /// bottom of the grid.
+    pub fn first_item_at_y(
+        &self,
+        ratios: &[f64],
+        y: f64,
+    ) -> Option<usize> {
+        let rows = self.get_rows(ratios);
+        let mut top = 0.0;
+
+        for (start, &(_, height)) in
+            Self::row_starts(&rows).zip(&rows)
+        {
+            let bottom = top + height;
+            if bottom > y {
+                return Some(start);
+            }
+            top = bottom + self.row_gap;
+        }
+        None
+    }
+
+    /// Strict grid of `columns` equal width columns, filled `columns` items
+    /// at a time. Every item is as high as its ratio asks for at the column
+    /// width, within `min_line_height..=max_line_height`, and rows are as
+    /// high as their tallest item. Heights moved by more than `epsilon` count
+    /// as clamped.
+    pub fn get_fixed_column_grid(
+        &self,
+        ratios: &[f64],
+        columns: u32,
+    ) -> FixedColumnLayout {
+        let columns = columns.max(1) as usize;
+        let column_width = (self.available_width
+            - self.gap * (columns - 1) as f64)
+            / columns as f64;
+        let mut items = Vec::with_capacity(ratios.len());
+        let mut clamped = Vec::new();
+        let mut y = 0.0;
+
+        for (row, chunk) in
+            ratios.chunks(columns).enumerate()
+        {
+            let mut row_height: f64 = 0.0;
+            for (column, &ratio) in chunk.iter().enumerate()
+            {
+                let index = row * columns + column;
+                let natural = column_width / ratio;
+                // Unlike `clamp`, doesn't panic on inverted or NaN bounds
+                let height = natural
+                    .max(self.min_line_height)
+                    .min(self.max_line_height);
+                if (height - natural).abs() > self.epsilon {
+                    clamped.push(index);
+                }
+                items.push(PositionedItem {
+                    index,
+                    x: column as f64
+                        * (column_width + self.gap),
+                    y,
+                    width: column_width,
+                    height,
+                    percent_width: 100.0 / columns as f64,
+                });
+                row_height = row_height.max(height);
+            }
+            y += row_height + self.row_gap;
+        }
+
+        FixedColumnLayout { items, clamped }
+    }
+
+    /// Amount of items held by `rows`
+    pub fn placed_item_count(rows: &[(u32, f64)]) -> usize {
+        rows.iter().map(|&(count, _)| count as usize).sum()
+    }
+
+    /// Same as `get_rows`, along with the amount of items that made it
+    pub fn get_rows_with_placed_count(
+        &self,
+        ratios: &[f64],
+    ) -> (Vec<(u32, f64)>, usize) {
+        let rows = self.get_rows(ratios);
+        let placed = Self::placed_item_count(&rows);
+        (rows, placed)
+    }
+}
+
+/// Input of the row producing exports once `nan_policy` is applied, errors
+/// with `Empty` when no item is left
+fn export_ratios(
+    grid: &ImageGrid,
+    ratios: &[f64],
+) -> Result<Vec<f64>, ResizeError> {
+    let (ratios, _) = grid.apply_nan_policy(ratios)?;
+    if ratios.is_empty() {
+        return Err(ResizeError::Empty);
+    }
+    Ok(ratios)
+}
+
+/// Per item heights. `epsilon` is the tolerance of width comparisons, pass the
+/// same value on server and client to get identical layouts. `last_row` takes
+/// the values of `GridConfig::last_row`, `undefined` keeps `"Justify"`. Throws
+/// on NaN ratios and when there are none.
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
+pub fn get_optimal_grid(
+    ratios: Vec<f64>,
+    available_width: f64,
+    min_line_height: f64,
+    max_line_height: f64,
+    min_item_width: f64,
+    gap: f64,
+    epsilon: Option<f64>,
+    max_items_per_row: Option<u32>,
+    last_row: JsValue,
+) -> Result<js_sys::Array, JsValue> {
+    let last_row = if last_row.is_undefined() {
+        LastRowBehavior::default()
+    } else {
+        serde_wasm_bindgen::from_value(last_row)?
+    };
+    let grid = ImageGrid {
+        epsilon: epsilon
+            .unwrap_or(ImageGrid::DEFAULT_EPSILON),
+        max_items_per_row,
+        last_row,
+        ..ImageGrid::try_new(
+            available_width,
+            min_line_height,
+            max_line_height,
+            min_item_width,
+            gap,
+        )?
+    };
+    let mut ratios = export_ratios(&grid, &ratios)?;
+
+    let height_list =
+        grid.get_row_from_items(&mut ratios).into_iter();
+
+    Ok(height_list
+        .flat_map(|(count, height)| {
+            std::iter::repeat_n(height, count as usize)
+        })
+        .map(JsValue::from)
+        .collect::<js_sys::Array>())
+}
+
+/// Same heights as `get_optimal_grid`, with `sentinel` (`-1` by default)
+/// pushed between rows. Heights are never negative, so any negative sentinel
+/// can't be mistaken for one.
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
+pub fn get_optimal_grid_with_separators(
+    ratios: Vec<f64>,
+    available_width: f64,
+    min_line_height: f64,
+    max_line_height: f64,
+    min_item_width: f64,
+    gap: f64,
+    sentinel: Option<f64>,
+    epsilon: Option<f64>,
+) -> Result<js_sys::Array, JsValue> {
+    let grid = ImageGrid {
+        epsilon: epsilon
+            .unwrap_or(ImageGrid::DEFAULT_EPSILON),
+        ..ImageGrid::try_new(
+            available_width,
+            min_line_height,
+            max_line_height,
+            min_item_width,
+            gap,
+        )?
+    };
+    let ratios = export_ratios(&grid, &ratios)?;
+    let sentinel = sentinel.unwrap_or(-1.0);
+
+    Ok(grid
+        .get_rows(&ratios)
+        .into_iter()
+        .enumerate()
+        .flat_map(|(row, (count, height))| {
+            let separator =
+                (row > 0).then_some(sentinel).into_iter();
+            separator.chain(std::iter::repeat_n(
+                height,
+                count as usize,
+            ))
+        })
+        .map(JsValue::from)
+        .collect::<js_sys::Array>())
+}
+
+/// Position and size of every item, in the original order
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
+pub fn get_optimal_grid_layout(
+    ratios: Vec<f64>,
+    available_width: f64,
+    min_line_height: f64,
+    max_line_height: f64,
+    min_item_width: f64,
+    gap: f64,
+    row_gap: f64,
+    epsilon: Option<f64>,
+) -> Result<Vec<GridItemLayout>, JsValue> {
+    let grid = ImageGrid {
+        row_gap,
+        epsilon: epsilon
+            .unwrap_or(ImageGrid::DEFAULT_EPSILON),
+        ..ImageGrid::try_new(
+            available_width,
+            min_line_height,
+            max_line_height,
+            min_item_width,
+            gap,
+        )?
+    };
+
+    let ratios = export_ratios(&grid, &ratios)?;
+
+    Ok(grid
+        .get_positioned_layout(&ratios)
+        .into_iter()
+        .map(GridItemLayout::from)
+        .collect())
+}
+
+/// Rendered height of the whole grid, `row_gap` included
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
+pub fn get_grid_total_height(
+    ratios: Vec<f64>,
+    available_width: f64,
+    min_line_height: f64,
+    max_line_height: f64,
+    min_item_width: f64,
+    gap: f64,
+    row_gap: f64,
+    epsilon: Option<f64>,
+) -> Result<f64, JsValue> {
+    let grid = ImageGrid {
+        row_gap,
+        epsilon: epsilon
+            .unwrap_or(ImageGrid::DEFAULT_EPSILON),
+        ..ImageGrid::try_new(
+            available_width,
+            min_line_height,
+            max_line_height,
+            min_item_width,
+            gap,
+        )?
+    };
+
+    let mut ratios = export_ratios(&grid, &ratios)?;
+
+    Ok(grid.total_height(&mut ratios))
+}
+
+/// Rows as `{ height, start_index, count }` objects, indices refer to the
+/// order of `ratios`
+#[wasm_bindgen]
+pub fn get_grid_rows(
+    ratios: Vec<f64>,
+    available_width: f64,
+    min_line_height: f64,
+    max_line_height: f64,
+    min_item_width: f64,
+    gap: f64,
+    epsilon: Option<f64>,
+) -> Result<JsValue, JsValue> {
+    let grid = ImageGrid {
+        epsilon: epsilon
+            .unwrap_or(ImageGrid::DEFAULT_EPSILON),
+        ..ImageGrid::try_new(
+            available_width,
+            min_line_height,
+            max_line_height,
+            min_item_width,
+            gap,
+        )?
+    };
+    let ratios = export_ratios(&grid, &ratios)?;
+    let rows = grid.get_rows(&ratios);
+    let rows =
+        ImageGrid::row_starts(&rows)
+            .zip(&rows)
+            .map(|(start_index, &(count, height))| {
+                GridRow { height, start_index, count }
+            })
+            .collect::<Vec<_>>();
+
+    Ok(serde_wasm_bindgen::to_value(&rows)?)
+}
+
+/// Strict grid of `columns` equal columns as `{ items, clamped }`, see
+/// `ImageGrid::get_fixed_column_grid`
+#[wasm_bindgen]
+pub fn get_fixed_column_grid(
+    ratios: Vec<f64>,
+    available_width: f64,
+    min_line_height: f64,
+    max_line_height: f64,
+    gap: f64,
+    columns: u32,
+    epsilon: Option<f64>,
+) -> Result<JsValue, JsValue> {
+    let grid = ImageGrid {
+        epsilon: epsilon
+            .unwrap_or(ImageGrid::DEFAULT_EPSILON),
+        ..ImageGrid::try_new(
+            available_width,
+            min_line_height,
+            max_line_height,
+            0.0,
+            gap,
+        )?
+    };
+    let ratios = export_ratios(&grid, &ratios)?;
+    let layout =
+        grid.get_fixed_column_grid(&ratios, columns);
+
+    Ok(serde_wasm_bindgen::to_value(&layout)?)
+}
+
+#[wasm_bindgen]
+pub fn get_optimal_grid_soa(
+    ratios: Vec<f64>,
+    available_width: f64,
+    min_line_height: f64,
+    max_line_height: f64,
+    min_item_width: f64,
+    gap: f64,
+    epsilon: Option<f64>,
+) -> Result<LayoutSoA, JsValue> {
+    let grid = ImageGrid {
+        epsilon: epsilon
+            .unwrap_or(ImageGrid::DEFAULT_EPSILON),
+        ..ImageGrid::try_new(
+            available_width,
+            min_line_height,
+            max_line_height,
+            min_item_width,
+            gap,
+        )?
+    };
+
+    let ratios = export_ratios(&grid, &ratios)?;
+
+    Ok(grid.get_positioned_soa(&ratios))
+}
+
+/// Rows, per row slack, clamp states, constraint errors and the indices
+/// skipped by `nan_policy` as a JS object, meant to be logged in dev builds
+#[wasm_bindgen]
+pub fn get_optimal_grid_debug(
+    ratios: Vec<f64>,
+    config: JsValue,
+) -> Result<JsValue, JsValue> {
+    let config: GridConfig =
+        serde_wasm_bindgen::from_value(config)?;
+    let report = config.to_grid()?.layout_report(&ratios);
+
+    Ok(serde_wasm_bindgen::to_value(&report)?)
+}
+
+/// Amount of rows only, computed one row at a time without building them
+#[wasm_bindgen]
+pub fn get_row_count(
+    ratios: Vec<f64>,
+    config: JsValue,
+) -> Result<u32, JsValue> {
+    let config: GridConfig =
+        serde_wasm_bindgen::from_value(config)?;
+    let grid = config.to_grid()?;
+
+    let (ratios, _) = grid.apply_nan_policy(&ratios)?;
+
+    Ok(grid.rows_iter(&ratios).count() as u32)
+}
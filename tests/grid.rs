@@ -1,6 +1,20 @@
 #[cfg(test)]
 mod tests {
     use perfect_grid::*;
+    use std::rc::Rc;
+
+    /// 800px wide gapless grid, rows between 200 and 500px high
+    /// and items at least 180px wide
+    fn base_grid() -> ImageGrid {
+        ImageGrid {
+            available_width: 800.0,
+            gap: 0.0,
+            min_line_height: 200.0,
+            max_line_height: 500.0,
+            min_item_width: 180.0,
+            ..Default::default()
+        }
+    }
 
     #[test]
     fn must_fit_4_items() {
@@ -16,6 +30,7 @@ mod tests {
             max_line_height: 641.0,
             gap: 4.0,
             min_item_width: 175.0,
+            ..Default::default()
         };
         let result = inst.get_optimal_height(&ratios);
 
@@ -38,6 +53,7 @@ mod tests {
             min_line_height: 200.0,
             max_line_height: 444.0,
             min_item_width: 175.0,
+            ..Default::default()
         };
         assert_eq!(
             inst.get_row_from_items(&mut ratios),
@@ -51,9 +67,7 @@ mod tests {
         let inst = ImageGrid {
             available_width: 1602.0,
             gap: 4.0,
-            min_line_height: 200.0,
-            max_line_height: 500.0,
-            min_item_width: 180.0,
+            ..base_grid()
         };
 
         assert_eq!(
@@ -65,27 +79,18 @@ mod tests {
     #[test]
     fn must_fit_4_squares() {
         let mut ratios = vec![1.0; 4];
-        let inst = ImageGrid {
-            available_width: 800.0,
-            gap: 0.0,
-            min_line_height: 200.0,
-            max_line_height: 500.0,
-            min_item_width: 180.0,
-        };
+        let inst = base_grid();
 
-        assert_eq!(inst.get_row_from_items(&mut ratios), [(4, 200.0)]);
+        assert_eq!(
+            inst.get_row_from_items(&mut ratios),
+            [(4, 200.0)]
+        );
     }
 
     #[test]
     fn fit_5th_square_next_line() {
         let mut ratios = vec![1.0; 5];
-        let inst = ImageGrid {
-            available_width: 800.0,
-            gap: 0.0,
-            min_line_height: 200.0,
-            max_line_height: 500.0,
-            min_item_width: 180.0,
-        };
+        let inst = base_grid();
 
         assert_eq!(
             inst.get_row_from_items(&mut ratios),
@@ -94,7 +99,9 @@ mod tests {
     }
 
     #[test]
-    #[should_panic(expected = "Min height can not be bigger than max height")]
+    #[should_panic(
+        expected = "Min height can not be bigger than max height"
+    )]
     fn test_new_min_height_bigger_than_max_height() {
         let available_width = 1000.0;
         let min_line_height = 200.0;
@@ -112,7 +119,9 @@ mod tests {
     }
 
     #[test]
-    #[should_panic(expected = "Available width can not be less than min item width")]
+    #[should_panic(
+        expected = "Available width can not be less than min item width"
+    )]
     fn test_new_available_width_less_than_min_item_width() {
         let available_width = 40.0;
         let min_line_height = 100.0;
@@ -159,11 +168,2101 @@ mod tests {
             max_line_height: 575.0,
             min_item_width: 175.0,
             min_line_height: 200.0,
+            ..Default::default()
         };
 
         assert_eq!(
             inst.get_row_from_items(&mut ratios),
-            [(4, 343.0), (4, 244.0), (4, 361.0), (5, 213.0), (3, 575.0),]
+            [
+                (4, 343.0),
+                (4, 244.0),
+                (4, 361.0),
+                (5, 213.0),
+                (3, 575.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn items_per_row_limit_grows_with_width() {
+        let limit = |width: f64| (width / 300.0) as u32;
+        let narrow = ImageGrid {
+            available_width: 600.0,
+            min_line_height: 100.0,
+            max_line_height: 1000.0,
+            min_item_width: 100.0,
+            gap: 0.0,
+            items_per_row_limit: Some(Rc::new(limit)),
+            ..Default::default()
+        };
+        let wide = ImageGrid {
+            available_width: 1200.0,
+            min_line_height: 100.0,
+            max_line_height: 1000.0,
+            min_item_width: 100.0,
+            gap: 0.0,
+            items_per_row_limit: Some(Rc::new(limit)),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            narrow.get_row_from_items(&mut vec![1.0; 6]),
+            [(2, 300.0), (2, 300.0), (2, 300.0)]
+        );
+        assert_eq!(
+            wide.get_row_from_items(&mut vec![1.0; 6]),
+            [(4, 300.0), (2, 600.0)]
+        );
+    }
+
+    #[test]
+    fn would_reflow_only_when_breaks_move() {
+        let ratios = vec![1.0; 5];
+        let inst = base_grid();
+        let current = inst.get_rows(&ratios);
+
+        assert_eq!(current, [(4, 200.0), (1, 500.0)]);
+        assert!(
+            !inst.would_reflow(&ratios, &current, 810.0)
+        );
+        assert!(
+            inst.would_reflow(&ratios, &current, 1000.0)
+        );
+    }
+
+    #[test]
+    fn css_grid_lists_every_row_height() {
+        let ratios: Vec<f64> = vec![
+            (0.6678141135972461),
+            (1.5086206896551724),
+            (0.5623318385650224),
+            (0.6666666666666666),
+            (1.7396551724137932),
+            (1.7396551724137932),
+        ];
+        let inst = ImageGrid {
+            available_width: 1526.0,
+            gap: 4.0,
+            min_line_height: 200.0,
+            max_line_height: 444.0,
+            min_item_width: 175.0,
+            ..Default::default()
+        };
+        let css =
+            inst.to_css_grid_scoped(&ratios, ".gallery");
+
+        assert!(css.starts_with(".gallery {\n"));
+        assert!(css.contains(
+            "  grid-template-rows: 444px 437px;\n"
+        ));
+        assert!(css.contains("  column-gap: 4px;\n"));
+        assert!(css.contains("  row-gap: 0px;\n}\n"));
+        assert_eq!(
+            css.matches(".gallery > :nth-child(").count(),
+            6
+        );
+        assert!(css.contains(
+            ".gallery > :nth-child(5) { grid-row: 2;"
+        ));
+    }
+
+    #[test]
+    fn row_slices_cover_the_input() {
+        let ratios: Vec<f64> = vec![
+            (0.6678141135972461),
+            (1.5086206896551724),
+            (0.5623318385650224),
+            (0.6666666666666666),
+            (1.7396551724137932),
+            (1.7396551724137932),
+        ];
+        let inst = ImageGrid {
+            available_width: 1526.0,
+            gap: 4.0,
+            min_line_height: 200.0,
+            max_line_height: 444.0,
+            min_item_width: 175.0,
+            ..Default::default()
+        };
+        let slices = inst.row_slices(&ratios);
+
+        assert_eq!(inst.break_indices(&ratios), [0, 4]);
+        assert_eq!(
+            slices
+                .iter()
+                .flat_map(|(row, _)| row.iter().copied())
+                .collect::<Vec<_>>(),
+            ratios
+        );
+        assert_eq!(
+            slices
+                .iter()
+                .map(|(row, height)| (
+                    row.len() as u32,
+                    *height
+                ))
+                .collect::<Vec<_>>(),
+            inst.get_rows(&ratios)
+        );
+    }
+
+    #[test]
+    fn single_row_threshold_keeps_small_inputs_together() {
+        let inst = ImageGrid {
+            available_width: 800.0,
+            gap: 0.0,
+            min_line_height: 300.0,
+            max_line_height: 500.0,
+            min_item_width: 300.0,
+            single_row_threshold: 4,
+            ..Default::default()
+        };
+
+        assert_eq!(inst.get_rows(&[1.0; 3]), [(3, 266.0)]);
+        assert_eq!(
+            inst.get_rows(&[1.0; 5]),
+            [(2, 400.0), (2, 400.0), (1, 500.0)]
+        );
+    }
+
+    #[test]
+    fn epsilon_decides_borderline_fit() {
+        let strict = ImageGrid {
+            available_width: 800.0,
+            gap: 0.0,
+            min_line_height: 200.0,
+            max_line_height: 500.0,
+            min_item_width: 200.5,
+            epsilon: 0.0,
+            ..Default::default()
+        };
+        let tolerant = ImageGrid {
+            available_width: 800.0,
+            gap: 0.0,
+            min_line_height: 200.0,
+            max_line_height: 500.0,
+            min_item_width: 200.5,
+            epsilon: 1.0,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            ImageGrid::default().epsilon,
+            ImageGrid::DEFAULT_EPSILON
+        );
+        assert_eq!(
+            strict.get_rows(&[1.0; 5]),
+            [(3, 266.0), (2, 400.0)]
+        );
+        assert_eq!(
+            tolerant.get_rows(&[1.0; 5]),
+            [(4, 200.0), (1, 500.0)]
+        );
+    }
+
+    #[test]
+    fn positioned_subset_keeps_original_indices() {
+        let ratios = [1.0, 1.0, 2.0, 1.0, 0.5, 1.0];
+        let inst =
+            ImageGrid { row_gap: 10.0, ..base_grid() };
+        let subset = [1, 3, 5]
+            .iter()
+            .map(|&index| (index, ratios[index]))
+            .collect::<Vec<_>>();
+        let layout = inst.get_positioned_subset(&subset);
+
+        assert_eq!(
+            layout
+                .iter()
+                .map(|item| item.index)
+                .collect::<Vec<_>>(),
+            [1, 3, 5]
+        );
+        assert_eq!(
+            layout,
+            inst.get_positioned_layout(&[1.0; 3])
+                .into_iter()
+                .zip([1, 3, 5])
+                .map(|(item, index)| PositionedItem {
+                    index,
+                    ..item
+                })
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(layout[2].x, 532.0);
+    }
+
+    #[test]
+    fn bottom_raggedness_scores_short_last_row() {
+        let inst = base_grid();
+        let rows_with_widths = |ratios: &[f64]| {
+            inst.row_slices(ratios)
+                .into_iter()
+                .map(|(row, height)| {
+                    row.iter()
+                        .map(|ratio| {
+                            (height * ratio, height)
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let justified = rows_with_widths(&[1.0; 4]);
+        let ragged = rows_with_widths(&[1.0; 5]);
+
+        assert!(inst.bottom_raggedness(&justified) < 1e-9);
+        assert_eq!(inst.bottom_raggedness(&ragged), 0.375);
+    }
+
+    #[test]
+    fn resize_session_matches_direct_layout() {
+        let ratios = [
+            1.5, 0.75, 1.0, 2.0, 0.5, 1.25, 0.75, 1.0, 3.0,
+        ];
+        let inst = ImageGrid {
+            available_width: 1200.0,
+            gap: 4.0,
+            min_line_height: 150.0,
+            max_line_height: 400.0,
+            min_item_width: 120.0,
+            ..Default::default()
+        };
+        let session = inst.resize_session(&ratios);
+
+        for width in [600.0, 800.0, 1024.0, 1280.0, 1920.0]
+        {
+            assert_eq!(
+                session.layout(width),
+                ImageGrid {
+                    available_width: width,
+                    gap: 4.0,
+                    min_line_height: 150.0,
+                    max_line_height: 400.0,
+                    min_item_width: 120.0,
+                    ..Default::default()
+                }
+                .get_rows(&ratios)
+            );
+        }
+    }
+
+    #[test]
+    fn max_items_in_row_counts_min_width_items() {
+        let inst = ImageGrid {
+            available_width: 1526.0,
+            gap: 4.0,
+            min_line_height: 200.0,
+            max_line_height: 444.0,
+            min_item_width: 175.0,
+            ..Default::default()
+        };
+        let single = ImageGrid {
+            available_width: 300.0,
+            gap: 4.0,
+            min_line_height: 200.0,
+            max_line_height: 444.0,
+            min_item_width: 175.0,
+            ..Default::default()
+        };
+
+        assert_eq!(inst.max_items_in_row(), 8);
+        assert_eq!(single.max_items_in_row(), 1);
+    }
+
+    #[test]
+    fn full_row_percent_widths_sum_to_100() {
+        let ratios = vec![
+            0.875,
+            0.875,
+            0.875,
+            16.0 / 9.0,
+            3.5555555555555554,
+            0.875,
+        ];
+        let inst = ImageGrid {
+            available_width: 1526.0,
+            gap: 4.0,
+            max_line_height: 575.0,
+            min_item_width: 175.0,
+            min_line_height: 200.0,
+            ..Default::default()
+        };
+        let layout = inst.get_positioned_layout(&ratios);
+        let first_row: f64 = layout
+            .iter()
+            .filter(|item| item.y == 0.0)
+            .map(|item| item.percent_width)
+            .sum();
+
+        assert!((first_row - 100.0).abs() < 0.5);
+    }
+
+    #[test]
+    fn gap_is_not_part_of_min_item_width() {
+        let rows = |min_item_width: f64| {
+            ImageGrid {
+                available_width: 400.0,
+                gap: 20.0,
+                min_line_height: 100.0,
+                max_line_height: 500.0,
+                min_item_width,
+                ..Default::default()
+            }
+            .get_rows(&[1.0, 1.0])
+        };
+
+        assert_eq!(rows(190.0), [(2, 190.0)]);
+        assert_eq!(rows(190.5), [(1, 400.0), (1, 400.0)]);
+
+        // Justified together the thin item would be 165px wide
+        let inst = ImageGrid {
+            available_width: 735.0,
+            gap: 20.0,
+            min_line_height: 200.0,
+            max_line_height: 800.0,
+            min_item_width: 175.0,
+            ..Default::default()
+        };
+        assert_eq!(
+            inst.get_rows(&[1.0, 0.3]),
+            [(1, 735.0), (1, 800.0)]
+        );
+        for item in inst.get_positioned_layout(&[1.0, 0.3])
+        {
+            assert!(item.width >= 175.0, "{item:?}");
+        }
+    }
+
+    #[test]
+    fn height_solvers_share_breaks() {
+        let inst = ImageGrid {
+            available_width: 800.0,
+            gap: 0.0,
+            min_line_height: 100.0,
+            max_line_height: 500.0,
+            min_item_width: 180.0,
+            ..Default::default()
+        };
+        let fill = inst.get_row_from_items_with(
+            &[1.0; 5],
+            &FillWidthSolver,
+        );
+        let target = inst.get_row_from_items_with(
+            &[1.0; 5],
+            &TargetHeightSolver { height: 150.0 },
+        );
+
+        assert_eq!(fill, inst.get_rows(&[1.0; 5]));
+        assert_eq!(fill, [(4, 200.0), (1, 500.0)]);
+        assert_eq!(target, [(4, 150.0), (1, 150.0)]);
+    }
+
+    #[test]
+    fn soa_layout_matches_positioned_layout() {
+        let ratios = [1.5, 0.75, 1.0, 2.0, 0.5, 1.25, 0.75];
+        let inst = ImageGrid {
+            available_width: 1000.0,
+            gap: 4.0,
+            row_gap: 8.0,
+            min_line_height: 150.0,
+            max_line_height: 400.0,
+            min_item_width: 120.0,
+            ..Default::default()
+        };
+        let soa = inst.get_positioned_soa(&ratios);
+        let aos = inst.get_positioned_layout(&ratios);
+
+        assert_eq!(
+            soa.xs,
+            aos.iter().map(|i| i.x).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            soa.ys,
+            aos.iter().map(|i| i.y).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            soa.widths,
+            aos.iter().map(|i| i.width).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            soa.heights,
+            aos.iter()
+                .map(|i| i.height)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn min_line_height_breaks_rows_earlier() {
+        let rows = |min_line_height: f64| {
+            ImageGrid {
+                available_width: 750.0,
+                gap: 0.0,
+                min_line_height,
+                max_line_height: 500.0,
+                min_item_width: 100.0,
+                ..Default::default()
+            }
+            .get_rows(&[1.0; 5])
+        };
+
+        assert_eq!(rows(100.0), [(5, 150.0)]);
+        assert_eq!(rows(200.0), [(3, 250.0), (2, 375.0)]);
+    }
+
+    #[test]
+    fn layout_report_describes_rows() {
+        let inst =
+            ImageGrid { row_gap: 10.0, ..base_grid() };
+        let report = inst.layout_report(&[1.0; 5]);
+
+        assert_eq!(
+            report.rows,
+            [
+                RowReport {
+                    count: 4,
+                    height: 200.0,
+                    slack: 0.0,
+                    clamped: false,
+                },
+                RowReport {
+                    count: 1,
+                    height: 500.0,
+                    slack: 300.0,
+                    clamped: true,
+                },
+            ]
+        );
+        assert_eq!(report.stats.total_height, 710.0);
+        assert!(report.errors.is_empty());
+        assert_eq!(
+            inst.layout_report(&[]).errors,
+            [ConstraintViolation {
+                row: None,
+                error: ResizeError::Empty,
+            }]
+        );
+    }
+
+    #[test]
+    fn priorities_pick_truncated_items() {
+        let inst =
+            ImageGrid { max_rows: Some(1), ..base_grid() };
+        let ratios = [1.0; 5];
+
+        assert_eq!(inst.get_rows(&ratios), [(4, 200.0)]);
+        assert_eq!(
+            inst.get_rows_with_priorities(
+                &ratios,
+                &[5, 5, 1, 5, 5]
+            ),
+            (vec![(4, 200.0)], vec![0, 1, 3, 4])
+        );
+    }
+
+    #[test]
+    fn finds_width_without_ragged_last_row() {
+        let inst = base_grid();
+        let ratios = [1.0; 5];
+
+        assert_eq!(
+            inst.width_for_full_last_row(
+                &ratios,
+                (800.0, 1200.0)
+            ),
+            Some(1000.0)
+        );
+        assert_eq!(
+            inst.width_for_full_last_row(
+                &ratios,
+                (800.0, 900.0)
+            ),
+            None
+        );
+        assert_eq!(
+            inst.width_for_full_last_row(
+                &ratios,
+                (800.0, f64::INFINITY)
+            ),
+            None
+        );
+        assert_eq!(
+            inst.width_for_full_last_row(
+                &ratios,
+                (f64::NAN, 1200.0)
+            ),
+            None
+        );
+        // 2px steps over ranges twice the step cap
+        assert_eq!(
+            inst.width_for_full_last_row(
+                &ratios,
+                (800.0, 800.0 + 8192.0)
+            ),
+            Some(1000.0)
+        );
+        assert!(inst
+            .width_for_full_last_row(&ratios, (800.0, 1e6))
+            .is_some());
+    }
+
+    #[test]
+    fn height_deltas_list_changed_items_only() {
+        let inst = base_grid();
+
+        assert_eq!(
+            inst.height_deltas(&[1.0; 5], 800.0, 810.0),
+            [
+                (0, 200.0, 202.0),
+                (1, 200.0, 202.0),
+                (2, 200.0, 202.0),
+                (3, 200.0, 202.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn unjustified_rows_stay_ragged() {
+        let inst = ImageGrid {
+            available_width: 800.0,
+            gap: 0.0,
+            min_line_height: 200.0,
+            max_line_height: 300.0,
+            min_item_width: 180.0,
+            justify_mode: JustifyMode::None,
+            ..Default::default()
+        };
+        let report = inst.layout_report(&[1.0; 5]);
+
+        assert_eq!(
+            inst.get_rows(&[1.0; 5]),
+            [(2, 300.0), (2, 300.0), (1, 300.0)]
+        );
+        assert!(report
+            .rows
+            .iter()
+            .all(|row| row.slack > 0.0));
+        assert_eq!(
+            inst.resize_session(&[1.0; 5]).layout(800.0),
+            inst.get_rows(&[1.0; 5])
+        );
+    }
+
+    #[test]
+    fn fit_grid_aspect_reaches_target_height() {
+        let inst = base_grid();
+        let max_line_height =
+            inst.fit_grid_aspect(&[1.0; 5], 16.0 / 9.0);
+        let rows = ImageGrid {
+            max_line_height,
+            available_width: 800.0,
+            gap: 0.0,
+            min_line_height: 200.0,
+            min_item_width: 180.0,
+            ..Default::default()
+        }
+        .get_rows(&[1.0; 5]);
+        let total: f64 =
+            rows.iter().map(|(_, height)| height).sum();
+
+        assert_eq!(max_line_height, 250.0);
+        assert!((total - 450.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn heights_for_manual_breaks() {
+        let inst = ImageGrid {
+            available_width: 800.0,
+            gap: 0.0,
+            min_line_height: 150.0,
+            max_line_height: 500.0,
+            min_item_width: 100.0,
+            ..Default::default()
+        };
+        let ratios = [1.0; 6];
+
+        assert_eq!(
+            inst.heights_for_breaks(&ratios, &[0, 2, 5]),
+            Ok(vec![400.0, 266.0, 500.0])
+        );
+        assert_eq!(
+            inst.heights_for_breaks(&ratios, &[0, 7]),
+            Err(ResizeError::InvalidBreaks)
+        );
+        assert_eq!(
+            inst.heights_for_breaks(&ratios, &[0]),
+            Err(ResizeError::LowerThanMinHeight)
+        );
+    }
+
+    #[test]
+    fn grouped_layout_stacks_groups() {
+        let inst =
+            ImageGrid { row_gap: 10.0, ..base_grid() };
+        let items = [
+            ("2023", 1.0),
+            ("2023", 1.0),
+            ("2024", 1.0),
+            ("2023", 1.0),
+            ("2023", 1.0),
+            ("2023", 1.0),
+        ];
+        let groups = inst.get_grouped_layout(&items, 40.0);
+
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].0, "2023");
+        assert_eq!(groups[0].2, 0.0);
+        assert_eq!(
+            groups[0]
+                .1
+                .iter()
+                .map(|item| item.index)
+                .collect::<Vec<_>>(),
+            [0, 1, 3, 4, 5]
+        );
+        // Rows of 4 at 200px and 1 at 500px, plus the row gap
+        assert_eq!(groups[1].0, "2024");
+        assert_eq!(
+            groups[1].2,
+            200.0 + 10.0 + 500.0 + 40.0
+        );
+        assert_eq!(groups[1].1[0].index, 2);
+    }
+
+    #[test]
+    fn inset_shrinks_and_centers_boxes() {
+        let grid = |inset: f64| ImageGrid {
+            gap: 4.0,
+            row_gap: 4.0,
+            inset,
+            ..base_grid()
+        };
+        let plain =
+            grid(0.0).get_positioned_layout(&[1.0; 5]);
+        let inset =
+            grid(0.5).get_positioned_layout(&[1.0; 5]);
+
+        for (plain, inset) in plain.iter().zip(&inset) {
+            assert_eq!(inset.width, plain.width - 1.0);
+            assert_eq!(inset.height, plain.height - 1.0);
+            assert_eq!(
+                inset.x + inset.width / 2.0,
+                plain.x + plain.width / 2.0
+            );
+            assert_eq!(
+                inset.y + inset.height / 2.0,
+                plain.y + plain.height / 2.0
+            );
+        }
+    }
+
+    #[test]
+    fn optimal_rows_cost_no_more_than_greedy() {
+        let ratios = vec![
+            0.875,
+            0.875,
+            0.875,
+            16.0 / 9.0,
+            3.5555555555555554,
+            0.875,
+            0.875,
+            0.875,
+            0.6648401826484018,
+            0.875,
+            16.0 / 9.0,
+            0.875,
+            16.0 / 9.0,
+            16.0 / 9.0,
+            16.0 / 9.0,
+            0.875,
+            0.875,
+            0.875,
+            0.875,
+            0.875,
+        ];
+        let inst = ImageGrid {
+            available_width: 1526.0,
+            gap: 4.0,
+            max_line_height: 575.0,
+            min_item_width: 175.0,
+            min_line_height: 200.0,
+            ..Default::default()
+        };
+        let (rows, cost) =
+            inst.get_rows_optimal_with_cost(&ratios);
+        let greedy = inst.get_rows(&ratios);
+
+        assert_eq!(rows, inst.get_rows_optimal(&ratios));
+        assert_eq!(
+            rows.iter()
+                .map(|&(count, _)| count)
+                .sum::<u32>(),
+            ratios.len() as u32
+        );
+        assert_eq!(cost, inst.layout_cost(&ratios, &rows));
+        assert!(cost <= inst.layout_cost(&ratios, &greedy));
+    }
+
+    #[test]
+    fn ratio_change_leaves_earlier_rows_untouched() {
+        let ratios = vec![
+            0.875,
+            0.875,
+            0.875,
+            16.0 / 9.0,
+            3.5555555555555554,
+            0.875,
+            0.875,
+            0.875,
+            0.6648401826484018,
+            0.875,
+            16.0 / 9.0,
+            0.875,
+            16.0 / 9.0,
+            16.0 / 9.0,
+            16.0 / 9.0,
+            0.875,
+            0.875,
+            0.875,
+            0.875,
+            0.875,
+        ];
+        let inst = ImageGrid {
+            available_width: 1526.0,
+            gap: 4.0,
+            max_line_height: 575.0,
+            min_item_width: 175.0,
+            min_line_height: 200.0,
+            ..Default::default()
+        };
+
+        // Item 10 lives in the third row
+        let affected = inst.rows_affected_by_ratio_change(
+            &ratios, 10, 0.5,
+        );
+
+        assert_eq!(affected.start, 2);
+        assert!(!affected.is_empty());
+        assert!(inst
+            .rows_affected_by_ratio_change(
+                &ratios, 10, ratios[10]
+            )
+            .is_empty());
+    }
+
+    #[test]
+    fn max_ratio_sum_thins_portrait_rows() {
+        let grid = |max_ratio_sum_per_row: Option<f64>| {
+            ImageGrid {
+                available_width: 1200.0,
+                gap: 0.0,
+                min_line_height: 100.0,
+                max_line_height: 600.0,
+                min_item_width: 50.0,
+                max_ratio_sum_per_row,
+                ..Default::default()
+            }
+        };
+        let ratios = [0.5; 12];
+
+        assert_eq!(
+            grid(None).get_rows(&ratios),
+            [(12, 200.0)]
+        );
+        assert_eq!(
+            grid(Some(3.0)).get_rows(&ratios),
+            [(6, 400.0), (6, 400.0)]
+        );
+        assert_eq!(
+            grid(Some(3.0))
+                .resize_session(&ratios)
+                .layout(1200.0),
+            [(6, 400.0), (6, 400.0)]
+        );
+    }
+
+    #[test]
+    fn hit_test_ignores_gaps() {
+        let inst = ImageGrid {
+            available_width: 812.0,
+            gap: 4.0,
+            row_gap: 4.0,
+            ..base_grid()
+        };
+        let items = inst.get_positioned_layout(&[1.0; 5]);
+
+        assert!(items[1].contains(300.0, 100.0));
+        assert_eq!(
+            inst.hit_test(&items, 300.0, 100.0),
+            Some(1)
+        );
+        assert_eq!(
+            inst.hit_test(&items, 202.0, 100.0),
+            None
+        );
+        assert_eq!(
+            inst.hit_test(&items, 100.0, 202.0),
+            None
+        );
+        assert_eq!(
+            inst.hit_test(&items, 100.0, 300.0),
+            Some(4)
+        );
+    }
+
+    #[test]
+    fn pages_start_below_previous_pages() {
+        let ratios = vec![
+            0.875,
+            0.875,
+            0.875,
+            16.0 / 9.0,
+            3.5555555555555554,
+            0.875,
+            0.875,
+            0.875,
+            0.6648401826484018,
+            0.875,
+            16.0 / 9.0,
+            0.875,
+            16.0 / 9.0,
+            16.0 / 9.0,
+            16.0 / 9.0,
+            0.875,
+            0.875,
+            0.875,
+            0.875,
+            0.875,
+        ];
+        let inst = ImageGrid {
+            available_width: 1526.0,
+            gap: 4.0,
+            row_gap: 10.0,
+            max_line_height: 575.0,
+            min_item_width: 175.0,
+            min_line_height: 200.0,
+            ..Default::default()
+        };
+        let (first, first_offset) =
+            inst.get_rows_page(&ratios, 0, 2);
+        let (second, second_offset) =
+            inst.get_rows_page(&ratios, 1, 2);
+        let (last, _) = inst.get_rows_page(&ratios, 2, 2);
+
+        assert_eq!(first_offset, 0.0);
+        assert_eq!(first, [(4, 343.0), (4, 244.0)]);
+        assert_eq!(second, [(4, 361.0), (5, 213.0)]);
+        assert_eq!(last, [(3, 575.0)]);
+        // Page 0 height plus the gap before the next row
+        assert_eq!(
+            second_offset,
+            343.0 + 10.0 + 244.0 + 10.0
+        );
+        assert_eq!(
+            inst.rows_iter(&ratios).collect::<Vec<_>>(),
+            inst.get_rows(&ratios)
+        );
+    }
+
+    #[test]
+    fn absorbs_single_orphan_within_tolerance() {
+        let inst = ImageGrid {
+            available_width: 800.0,
+            gap: 0.0,
+            min_line_height: 195.0,
+            max_line_height: 500.0,
+            min_item_width: 5.0,
+            ..Default::default()
+        };
+        let ratios = [1.0, 1.0, 1.0, 1.0, 0.05];
+        // Rows of a grid with a higher min_line_height
+        let rows = vec![(4, 200.0), (1, 500.0)];
+
+        assert_eq!(
+            inst.absorb_single_orphan(
+                &ratios,
+                rows.clone(),
+                0.02
+            ),
+            [(5, 197.0)]
+        );
+        assert_eq!(
+            inst.absorb_single_orphan(
+                &ratios,
+                rows.clone(),
+                0.01
+            ),
+            rows
+        );
+
+        let stricter = ImageGrid {
+            min_line_height: 198.0,
+            ..inst.clone()
+        };
+        assert_eq!(
+            stricter.absorb_single_orphan(
+                &ratios,
+                rows.clone(),
+                0.02
+            ),
+            rows
+        );
+
+        let limited = ImageGrid {
+            max_items_per_row: Some(4),
+            ..inst
+        };
+        assert_eq!(
+            limited.absorb_single_orphan(
+                &ratios,
+                rows.clone(),
+                0.02
+            ),
+            rows
+        );
+    }
+
+    #[test]
+    fn nan_policies() {
+        let grid = |nan_policy: NanPolicy| ImageGrid {
+            nan_policy,
+            ..base_grid()
+        };
+        let ratios = [1.0, 1.0, f64::NAN, 1.0, 1.0];
+
+        assert_eq!(
+            grid(NanPolicy::default())
+                .get_rows_checked(&ratios),
+            Err(ResizeError::NanRatio)
+        );
+        assert_eq!(
+            grid(NanPolicy::Skip).get_rows_checked(&ratios),
+            Ok((vec![(4, 200.0)], vec![2]))
+        );
+        assert_eq!(
+            grid(NanPolicy::Substitute(1.0))
+                .get_rows_checked(&ratios),
+            Ok((vec![(4, 200.0), (1, 500.0)], vec![]))
+        );
+    }
+
+    #[test]
+    fn row_fractions_sum_to_one() {
+        let grid = ImageGrid {
+            available_width: 1000.0,
+            gap: 10.0,
+            min_line_height: 100.0,
+            max_line_height: 400.0,
+            min_item_width: 50.0,
+            ..Default::default()
+        };
+        let ratios = [1.0, 2.0, 1.5, 0.5, 1.0, 1.0, 3.0];
+        let fractions = grid.row_fractions(&ratios);
+
+        assert_eq!(
+            fractions.iter().map(Vec::len).sum::<usize>(),
+            ratios.len()
+        );
+        let mut ratios = ratios.iter();
+        for row in fractions {
+            assert!(
+                (row.iter().sum::<f64>() - 1.0).abs()
+                    < 1e-9
+            );
+            let row_ratios = ratios
+                .by_ref()
+                .take(row.len())
+                .collect::<Vec<_>>();
+            for (fraction, ratio) in
+                row.iter().zip(&row_ratios)
+            {
+                assert!(
+                    (fraction / *ratio
+                        - row[0] / row_ratios[0])
+                        .abs()
+                        < 1e-9
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn timed_layout_stops_on_budget() {
+        let grid = base_grid();
+        let ratios = [1.0; 10];
+
+        assert_eq!(
+            grid.get_rows_timed(&ratios, 1),
+            (vec![(4, 200.0)], false)
+        );
+        assert_eq!(
+            grid.get_rows_timed(&ratios, 1000),
+            (grid.get_rows(&ratios), true)
+        );
+        assert_eq!(
+            grid.get_rows_timed(&ratios, 0),
+            (vec![], false)
+        );
+        assert_eq!(
+            grid.get_rows_timed(&[], 0),
+            (vec![], true)
+        );
+    }
+
+    #[test]
+    fn builder_max_line_height_fraction() {
+        let grid = ImageGrid::builder()
+            .max_line_height_fraction(0.33)
+            .available_width(1500.0)
+            .min_line_height(200.0)
+            .min_item_width(180.0)
+            .gap(4.0)
+            .build();
+
+        assert!(
+            (grid.max_line_height - 495.0).abs() < 1e-9
+        );
+        assert_eq!(grid.available_width, 1500.0);
+        assert_eq!(grid.gap, 4.0);
+    }
+
+    #[test]
+    fn compression_ratio_of_constrained_width() {
+        let grid = base_grid();
+        let ratio = grid.compression_ratio(&[1.0; 5]);
+
+        assert!(ratio < 1.0);
+        assert!(
+            (ratio - 410_000.0 / 1_250_000.0).abs() < 1e-9
+        );
+        assert_eq!(grid.compression_ratio(&[]), 1.0);
+    }
+
+    #[test]
+    fn max_height_keeps_items_above_fold() {
+        let grid = |max_line_height: f64| ImageGrid {
+            available_width: 800.0,
+            gap: 0.0,
+            min_line_height: 100.0,
+            max_line_height,
+            min_item_width: 50.0,
+            items_per_row_limit: Some(Rc::new(|_| 2)),
+            ..Default::default()
+        };
+        let ratios = [1.0; 8];
+        let visible = |max_line_height: f64| {
+            let mut bottom = 0.0;
+            grid(max_line_height)
+                .get_rows(&ratios)
+                .into_iter()
+                .take_while(|&(_, height)| {
+                    bottom += height;
+                    bottom <= 450.0
+                })
+                .map(|(count, _)| count)
+                .sum::<u32>()
+        };
+        assert_eq!(visible(500.0), 2);
+
+        let height = grid(500.0)
+            .max_height_for_min_visible(&ratios, 450.0, 4);
+        assert_eq!(height, 225.0);
+        assert!(visible(height) >= 4);
+        assert_eq!(
+            grid(200.0).max_height_for_min_visible(
+                &ratios, 450.0, 2
+            ),
+            200.0
+        );
+    }
+
+    #[test]
+    fn row_range_bounds_include_gaps() {
+        let grid =
+            ImageGrid { row_gap: 10.0, ..base_grid() };
+        let ratios = [1.0; 9];
+
+        assert_eq!(
+            grid.get_rows(&ratios),
+            [(4, 200.0), (4, 200.0), (1, 500.0)]
+        );
+        assert_eq!(
+            grid.row_offsets(&ratios),
+            [0.0, 210.0, 420.0]
+        );
+        assert_eq!(
+            grid.row_range_bounds(&ratios, 1, 2),
+            Some((210.0, 710.0))
+        );
+        assert_eq!(
+            grid.row_range_bounds(&ratios, 2, 3),
+            None
+        );
+        assert_eq!(
+            grid.row_range_bounds(&ratios, 2, 1),
+            None
+        );
+    }
+
+    #[test]
+    fn densest_packs_more_items_per_row() {
+        let grid = ImageGrid {
+            available_width: 1000.0,
+            gap: 0.0,
+            min_line_height: 100.0,
+            max_line_height: 400.0,
+            min_item_width: 150.0,
+            ..Default::default()
+        };
+        let mut ratios =
+            vec![1.0, 0.4, 1.0, 1.0, 1.0, 1.0, 1.0];
+
+        assert_eq!(
+            grid.get_rows_densest(&ratios),
+            [(7, 156.0)]
+        );
+        assert_eq!(
+            grid.get_row_from_items(&mut ratios),
+            [(3, 400.0), (4, 250.0)]
+        );
+    }
+
+    #[test]
+    fn row_tension_of_clamped_row() {
+        let grid = base_grid();
+        let tension = grid.row_tension(&[1.0; 5]);
+
+        assert_eq!(tension.len(), 2);
+        assert!(tension[0].abs() < 1e-9);
+        // The last row holds 250px of its 400px fill height
+        assert!((tension[1] + 0.375).abs() < 1e-9);
+    }
+
+    #[test]
+    fn paginate_moves_rows_to_next_page() {
+        let grid =
+            ImageGrid { row_gap: 10.0, ..base_grid() };
+        let ratios = [1.0; 13];
+
+        assert_eq!(
+            grid.paginate(&ratios, 450.0),
+            [
+                vec![(4, 200.0), (4, 200.0)],
+                vec![(4, 200.0)],
+                vec![(1, 500.0)],
+            ]
+        );
+        assert_eq!(grid.paginate(&ratios, 409.0).len(), 4);
+    }
+
+    #[test]
+    fn best_fit_total_height_near_target() {
+        let grid = ImageGrid {
+            available_width: 800.0,
+            gap: 0.0,
+            min_line_height: 100.0,
+            max_line_height: 500.0,
+            min_item_width: 50.0,
+            items_per_row_limit: Some(Rc::new(|_| 3)),
+            ..Default::default()
+        };
+        let ratios = [1.5, 0.7, 1.0, 2.0, 0.8, 1.2, 1.0];
+        let target = 700.0;
+
+        let (max_line_height, rows) =
+            grid.best_fit_total_height(&ratios, target);
+        let total = rows
+            .iter()
+            .map(|&(_, height)| height)
+            .sum::<f64>();
+        let tallest = rows
+            .iter()
+            .map(|&(_, height)| height)
+            .fold(0.0, f64::max);
+
+        assert!(rows
+            .iter()
+            .all(|&(_, height)| height <= max_line_height));
+        assert!((total - target).abs() <= tallest);
+    }
+
+    #[test]
+    fn pinned_items_survive_truncation() {
+        let inst =
+            ImageGrid { max_rows: Some(1), ..base_grid() };
+        let ratios = [1.0; 9];
+
+        assert_eq!(
+            inst.get_rows_with_pinned(
+                &ratios,
+                &[6, 8],
+                None
+            ),
+            (vec![(4, 200.0)], vec![0, 1, 6, 8])
+        );
+
+        let inst = ImageGrid { max_rows: None, ..inst };
+        let (_, kept) = inst.get_rows_with_pinned(
+            &ratios,
+            &[8],
+            Some(3),
+        );
+        assert_eq!(kept, [0, 1, 8]);
+    }
+
+    #[test]
+    fn row_width_at_height_includes_gaps() {
+        let inst = ImageGrid { gap: 8.0, ..base_grid() };
+
+        // 200 * (1.5 + 0.75 + 1.0) + 2 gaps
+        assert_eq!(
+            inst.row_width_at_height(
+                &[1.5, 0.75, 1.0],
+                200.0
+            ),
+            666.0
+        );
+        assert_eq!(
+            inst.row_width_at_height(&[], 200.0),
+            0.0
+        );
+    }
+
+    #[test]
+    fn balance_tail_evens_last_rows() {
+        let inst = base_grid();
+        let ratios = [1.0; 5];
+        let rows = inst.get_rows(&ratios);
+
+        assert_eq!(rows, [(4, 200.0), (1, 500.0)]);
+        assert_eq!(
+            inst.balance_tail(&ratios, rows),
+            [(3, 266.0), (2, 400.0)]
+        );
+        assert_eq!(
+            inst.balance_tail(&ratios, vec![(5, 160.0)]),
+            [(5, 160.0)]
+        );
+
+        let ratios = [1.0, 1.0, 1.0, 3.0];
+        let limited = ImageGrid {
+            max_items_per_row: Some(2),
+            ..inst.clone()
+        };
+        let rows = limited.get_rows(&ratios);
+
+        assert_eq!(rows, [(2, 400.0), (2, 200.0)]);
+        assert_eq!(
+            limited.balance_tail(&ratios, rows.clone()),
+            rows
+        );
+        assert_eq!(
+            inst.balance_tail(&ratios, rows),
+            [(3, 266.0), (1, 266.0)]
+        );
+    }
+
+    #[test]
+    fn justify_then_scale_fits_max_rows() {
+        let inst = |max_rows, overflow_policy| ImageGrid {
+            max_rows,
+            overflow_policy,
+            ..base_grid()
+        };
+        let ratios = [
+            1.0, 1.5, 0.75, 1.0, 1.25, 0.8, 1.0, 1.5, 1.0,
+            0.75, 1.0, 1.0, 1.5,
+        ];
+        assert_eq!(
+            inst(None, OverflowPolicy::Truncate)
+                .get_rows(&ratios)
+                .len(),
+            5
+        );
+        assert_eq!(
+            inst(Some(3), OverflowPolicy::Truncate)
+                .get_rows(&ratios)
+                .len(),
+            3
+        );
+
+        let scaled =
+            inst(Some(3), OverflowPolicy::JustifyThenScale);
+        let rows = scaled.get_rows(&ratios);
+        assert_eq!(rows.len(), 3);
+        assert_eq!(
+            rows.iter()
+                .map(|&(count, _)| count)
+                .sum::<u32>(),
+            13
+        );
+
+        assert_eq!(
+            scaled.rows_iter(&ratios).collect::<Vec<_>>(),
+            rows
+        );
+        assert_eq!(
+            scaled.paginate(&ratios, 10_000.0),
+            vec![rows]
+        );
+
+        let positioned =
+            scaled.get_positioned_layout(&ratios);
+        assert_eq!(positioned.len(), 13);
+        for (item, ratio) in positioned.iter().zip(ratios) {
+            assert!(
+                (item.width / item.height - ratio).abs()
+                    < 1e-9
+            );
+        }
+    }
+
+    #[test]
+    fn distinct_row_heights_of_equals() {
+        let ratios = vec![0.875; 12];
+        let inst = ImageGrid {
+            available_width: 1602.0,
+            gap: 4.0,
+            ..base_grid()
+        };
+
+        assert_eq!(
+            inst.distinct_row_heights(&ratios),
+            [224.0, 454.0]
+        );
+        assert_eq!(
+            inst.distinct_row_heights(&[0.875; 16]),
+            [224.0]
+        );
+    }
+
+    #[test]
+    fn clamp_then_break_branches() {
+        let inst = |min_item_width, fit_policy| ImageGrid {
+            available_width: 800.0,
+            gap: 0.0,
+            min_line_height: 200.0,
+            max_line_height: 500.0,
+            min_item_width,
+            fit_policy,
+            ..Default::default()
+        };
+        let ratios = [1.0; 5];
+
+        // The last row would need 800px, it is clamped to max instead
+        let rows = inst(100.0, FitPolicy::ClampThenBreak)
+            .get_rows(&ratios[..1]);
+        assert_eq!(rows, [(1, 500.0)]);
+
+        // A fifth item would get the row down to 160px, it breaks earlier
+        let rows = inst(100.0, FitPolicy::ClampThenBreak)
+            .get_rows(&ratios);
+        assert_eq!(rows, [(4, 200.0), (1, 500.0)]);
+        assert_eq!(
+            inst(100.0, FitPolicy::ClampOnly)
+                .get_rows(&ratios),
+            [(5, 160.0)]
+        );
+        assert_eq!(
+            inst(180.0, FitPolicy::ClampOnly)
+                .get_rows(&ratios),
+            [(4, 200.0), (1, 500.0)]
+        );
+    }
+
+    #[test]
+    fn flex_grow_matches_ratios() {
+        let inst = base_grid();
+        let ratios = [1.0, 1.5, 0.75, 1.0, 1.25, 0.8, 1.0];
+        let flex = inst.flex_values(&ratios);
+
+        assert_eq!(
+            flex.len(),
+            inst.get_rows(&ratios).len()
+        );
+        let values = flex.concat();
+        assert_eq!(values.len(), ratios.len());
+        for (&(grow, basis), ratio) in
+            values.iter().zip(ratios)
+        {
+            assert_eq!(grow, ratio);
+            assert_eq!(basis, ratio * 200.0);
+        }
+    }
+
+    #[test]
+    fn try_new_reports_invalid_bounds() {
+        assert_eq!(
+            ImageGrid::try_new(
+                1000.0, 500.0, 200.0, 180.0, 4.0
+            )
+            .unwrap_err(),
+            ResizeError::InvalidHeightBounds
+        );
+        assert_eq!(
+            ImageGrid::try_new(
+                40.0, 200.0, 500.0, 180.0, 4.0
+            )
+            .unwrap_err(),
+            ResizeError::MinItemWidthOverload
+        );
+        assert!(ImageGrid::try_new(
+            1000.0, 200.0, 500.0, 180.0, 4.0
+        )
+        .is_ok());
+        for (min, max, width) in [
+            (f64::NAN, 400.0, 800.0),
+            (200.0, f64::INFINITY, 800.0),
+            (200.0, 400.0, f64::NAN),
+        ] {
+            assert_eq!(
+                ImageGrid::try_new(
+                    width, min, max, 0.0, 10.0
+                )
+                .unwrap_err(),
+                ResizeError::InvalidHeightBounds
+            );
+        }
+
+        let grid = ImageGrid::new(
+            1000.0, 200.0, 500.0, 180.0, 4.0,
+        );
+        assert_eq!(
+            grid.get_rows_checked(&[]),
+            Err(ResizeError::Empty)
+        );
+    }
+
+    #[test]
+    fn seeded_tie_breaking_is_reproducible() {
+        let grid = |seed| ImageGrid {
+            available_width: 800.0,
+            gap: 0.0,
+            min_line_height: 200.0,
+            max_line_height: 500.0,
+            min_item_width: 50.0,
+            seed,
+            ..Default::default()
+        };
+        let ratios = [2.0, 0.5, 1.0, 1.0, 1.0, 0.5, 0.5];
+        let (unseeded, cost) =
+            grid(None).get_rows_optimal_with_cost(&ratios);
+
+        assert_eq!(
+            grid(None).get_rows_optimal(&ratios),
+            unseeded
+        );
+        assert_eq!(
+            grid(Some(7)).get_rows_optimal(&ratios),
+            grid(Some(7)).get_rows_optimal(&ratios)
+        );
+
+        let mut layouts = Vec::new();
+        for seed in 0..16 {
+            let (rows, seeded_cost) = grid(Some(seed))
+                .get_rows_optimal_with_cost(&ratios);
+            assert_eq!(seeded_cost, cost);
+            if !layouts.contains(&rows) {
+                layouts.push(rows);
+            }
+        }
+        assert!(layouts.len() > 1);
+    }
+
+    #[test]
+    fn gap_keeps_widest_item_at_cap() {
+        let grid = |gap| ImageGrid {
+            available_width: 800.0,
+            gap,
+            min_line_height: 100.0,
+            max_line_height: 500.0,
+            min_item_width: 50.0,
+            rounding: HeightRounding::Exact,
+            ..Default::default()
+        };
+        let ratios = [1.0, 2.0, 1.0];
+
+        let gap = grid(0.0)
+            .gap_for_max_item_width(&ratios, 300.0);
+        assert_eq!(gap, 100.0);
+        let height = grid(gap).get_optimal_height(&ratios);
+        assert_eq!(height * 2.0, 300.0);
+        assert_eq!(
+            grid(0.0)
+                .gap_for_max_item_width(&ratios, 500.0),
+            0.0
+        );
+    }
+
+    #[test]
+    fn max_items_per_row_forces_breaks() {
+        let inst = |max_items_per_row| ImageGrid {
+            available_width: 800.0,
+            gap: 0.0,
+            min_line_height: 100.0,
+            max_line_height: 500.0,
+            min_item_width: 100.0,
+            max_items_per_row,
+            ..Default::default()
+        };
+        let ratios = [1.0; 7];
+
+        assert_eq!(
+            inst(None).get_rows(&ratios),
+            [(7, 114.0)]
+        );
+        assert_eq!(
+            inst(Some(3)).get_rows(&ratios),
+            [(3, 266.0), (3, 266.0), (1, 500.0)]
+        );
+
+        let inst = ImageGrid {
+            items_per_row_limit: Some(Rc::new(|_| 2)),
+            ..inst(Some(3))
+        };
+        assert_eq!(
+            inst.get_rows(&ratios[..4]),
+            [(2, 400.0), (2, 400.0)]
+        );
+    }
+
+    #[test]
+    fn snapshot_string_format() {
+        let inst = ImageGrid {
+            rounding: HeightRounding::Exact,
+            ..base_grid()
+        };
+
+        assert_eq!(
+            inst.to_snapshot_string(&[1.0; 5]),
+            "4 x 200.00\n1 x 500.00\n"
+        );
+        assert_eq!(
+            inst.to_snapshot_string(&[1.0; 3]),
+            "3 x 266.67\n"
+        );
+        assert_eq!(inst.to_snapshot_string(&[]), "");
+    }
+
+    #[test]
+    fn post_inset_gaps_fit_without_gaps() {
+        let inst = |gap, gap_mode| ImageGrid {
+            available_width: 800.0,
+            gap,
+            min_line_height: 200.0,
+            max_line_height: 500.0,
+            min_item_width: 180.0,
+            gap_mode,
+            ..Default::default()
+        };
+        let ratios = [1.0; 5];
+
+        assert_eq!(
+            inst(8.0, GapComputation::Inline)
+                .get_rows(&ratios),
+            [(3, 261.0), (2, 396.0)]
+        );
+        assert_eq!(
+            inst(8.0, GapComputation::PostInset)
+                .get_rows(&ratios),
+            inst(0.0, GapComputation::Inline)
+                .get_rows(&ratios)
+        );
+
+        let layout = inst(8.0, GapComputation::PostInset)
+            .get_positioned_layout(&ratios);
+        let first_row = &layout[..4];
+        let right_edge =
+            first_row[3].x + first_row[3].width;
+        assert!((right_edge - 800.0).abs() < 1e-9);
+        assert!(first_row
+            .iter()
+            .all(|item| item.height == 200.0
+                && item.width == 194.0));
+
+        let post_inset =
+            inst(8.0, GapComputation::PostInset);
+        assert_eq!(
+            post_inset
+                .rows_iter(&ratios)
+                .collect::<Vec<_>>(),
+            post_inset.get_rows(&ratios)
+        );
+        let report = post_inset.layout_report(&ratios);
+        assert_eq!(
+            report.errors,
+            inst(0.0, GapComputation::Inline)
+                .layout_report(&ratios)
+                .errors
+        );
+        assert!(report
+            .rows
+            .iter()
+            .all(|row| row.slack >= 0.0));
+    }
+
+    #[test]
+    fn total_height_matches_positioned_layout() {
+        let inst =
+            ImageGrid { row_gap: 12.0, ..base_grid() };
+        let ratios = vec![1.0; 9];
+
+        assert_eq!(
+            inst.total_height(&mut ratios.clone()),
+            200.0 + 200.0 + 500.0 + 24.0
+        );
+        let bottom = inst
+            .get_positioned_layout(&ratios)
+            .iter()
+            .map(|item| item.y + item.height)
+            .fold(0.0, f64::max);
+        assert_eq!(
+            inst.total_height(&mut ratios.clone()),
+            bottom
+        );
+    }
+
+    #[test]
+    fn first_item_at_scroll_offset() {
+        let inst =
+            ImageGrid { row_gap: 10.0, ..base_grid() };
+        let ratios = [1.0; 9];
+
+        assert_eq!(
+            inst.first_item_at_y(&ratios, 0.0),
+            Some(0)
+        );
+        assert_eq!(
+            inst.first_item_at_y(&ratios, 250.0),
+            Some(4)
+        );
+        assert_eq!(
+            inst.first_item_at_y(&ratios, 205.0),
+            Some(4)
+        );
+        assert_eq!(
+            inst.first_item_at_y(&ratios, 500.0),
+            Some(8)
+        );
+        assert_eq!(
+            inst.first_item_at_y(&ratios, 920.0),
+            None
+        );
+    }
+
+    #[test]
+    fn last_row_behaviors() {
+        let inst = |last_row| ImageGrid {
+            last_row,
+            ..base_grid()
+        };
+        let ratios = vec![1.0; 5];
+
+        assert_eq!(
+            inst(LastRowBehavior::default())
+                .get_row_from_items(&mut ratios.clone()),
+            [(4, 200.0), (1, 500.0)]
+        );
+        assert_eq!(
+            inst(LastRowBehavior::MatchPrevious)
+                .get_row_from_items(&mut ratios.clone()),
+            [(4, 200.0), (1, 200.0)]
+        );
+        assert_eq!(
+            inst(LastRowBehavior::Clamp(300.0))
+                .get_row_from_items(&mut ratios.clone()),
+            [(4, 200.0), (1, 300.0)]
+        );
+        // Rows filling the width are left alone
+        assert_eq!(
+            inst(LastRowBehavior::Clamp(100.0))
+                .get_row_from_items(&mut vec![1.0; 8]),
+            [(4, 200.0), (4, 200.0)]
+        );
+        assert_eq!(
+            inst(LastRowBehavior::MatchPrevious)
+                .get_row_from_items(&mut vec![1.0]),
+            [(1, 500.0)]
+        );
+    }
+
+    #[test]
+    fn rows_iter_applies_last_row() {
+        let behaviors = [
+            LastRowBehavior::Justify,
+            LastRowBehavior::MatchPrevious,
+            LastRowBehavior::Clamp(300.0),
+        ];
+        for last_row in behaviors {
+            let inst =
+                ImageGrid { last_row, ..base_grid() };
+            for ratios in
+                [vec![1.0; 5], vec![1.0; 8], vec![1.0]]
+            {
+                let rows = inst.get_rows(&ratios);
+
+                assert_eq!(
+                    inst.rows_iter(&ratios)
+                        .collect::<Vec<_>>(),
+                    rows
+                );
+                assert_eq!(
+                    inst.paginate(&ratios, 10_000.0),
+                    vec![rows]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn large_gallery_does_not_overflow_the_stack() {
+        let inst = ImageGrid { gap: 4.0, ..base_grid() };
+        let mut ratios =
+            [1.5, 0.75, 1.0, 1.33, 0.66].repeat(40_000);
+        let rows =
+            inst.get_row_from_items(&mut ratios.clone());
+
+        assert_eq!(
+            rows.iter()
+                .map(|&(count, _)| count as usize)
+                .sum::<usize>(),
+            ratios.len()
+        );
+        assert_eq!(
+            rows,
+            inst.rows_iter(&ratios).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            inst.get_row_from_items(&mut ratios),
+            rows
+        );
+    }
+
+    #[test]
+    fn last_row_justify_threshold_keeps_sparse_rows_natural(
+    ) {
+        let inst = ImageGrid {
+            available_width: 1000.0,
+            last_row_justify_threshold: 0.6,
+            ..base_grid()
+        };
+
+        // 30% of the width at the previous height, left natural
+        assert_eq!(
+            inst.get_rows(&[1.0, 1.0, 1.0, 1.0, 1.0, 1.5]),
+            [(5, 200.0), (1, 200.0)]
+        );
+        // 70%, justified to fill the width
+        assert_eq!(
+            inst.get_rows(&[
+                1.0, 1.0, 1.0, 1.0, 1.0, 1.5, 2.0
+            ]),
+            [(5, 200.0), (2, 285.0)]
+        );
+        assert_eq!(
+            ImageGrid {
+                last_row_justify_threshold: 0.0,
+                ..inst.clone()
+            }
+            .get_rows(&[1.0, 1.0, 1.0, 1.0, 1.0, 1.5]),
+            [(5, 200.0), (1, 500.0)]
+        );
+        // Below the threshold last_row decides
+        let clamped = ImageGrid {
+            last_row: LastRowBehavior::Clamp(120.0),
+            ..inst.clone()
+        };
+        assert_eq!(
+            clamped
+                .get_rows(&[1.0, 1.0, 1.0, 1.0, 1.0, 1.5]),
+            [(5, 200.0), (1, 120.0)]
+        );
+        assert_eq!(
+            clamped
+                .rows_iter(&[1.0, 1.0, 1.0, 1.0, 1.0, 1.5])
+                .collect::<Vec<_>>(),
+            [(5, 200.0), (1, 120.0)]
+        );
+        assert_eq!(
+            ImageGrid {
+                last_row: LastRowBehavior::MatchPrevious,
+                ..inst
+            }
+            .get_rows(&[1.0, 1.0, 1.0, 1.0, 1.0, 1.5]),
+            [(5, 200.0), (1, 200.0)]
+        );
+    }
+
+    #[test]
+    fn fixed_column_grid_reports_clamping() {
+        let inst = ImageGrid {
+            available_width: 830.0,
+            gap: 10.0,
+            row_gap: 20.0,
+            min_line_height: 100.0,
+            max_line_height: 400.0,
+            min_item_width: 0.0,
+            ..Default::default()
+        };
+        let layout = inst.get_fixed_column_grid(
+            &[1.0, 2.0, 0.5, 1.0, 4.0],
+            4,
+        );
+
+        assert_eq!(
+            layout
+                .items
+                .iter()
+                .map(|item| (item.x, item.y, item.height))
+                .collect::<Vec<_>>(),
+            [
+                (0.0, 0.0, 200.0),
+                (210.0, 0.0, 100.0),
+                (420.0, 0.0, 400.0),
+                (630.0, 0.0, 200.0),
+                (0.0, 420.0, 100.0),
+            ]
+        );
+        assert!(layout
+            .items
+            .iter()
+            .all(|item| item.width == 200.0));
+        assert_eq!(layout.clamped, [4]);
+    }
+
+    #[test]
+    fn placed_count_reflects_truncation() {
+        let inst = |max_rows| ImageGrid {
+            max_rows,
+            ..base_grid()
+        };
+        let ratios = [1.0; 9];
+
+        assert_eq!(
+            inst(None)
+                .get_rows_with_placed_count(&ratios)
+                .1,
+            ratios.len()
+        );
+        let (rows, placed) = inst(Some(2))
+            .get_rows_with_placed_count(&ratios);
+        assert_eq!(placed, 8);
+        assert_eq!(
+            ImageGrid::placed_item_count(&rows),
+            placed
+        );
+    }
+
+    #[test]
+    fn rounding_modes() {
+        let inst = |rounding| ImageGrid {
+            rounding,
+            ..base_grid()
+        };
+        let ratios = [1.0; 3];
+
+        assert_eq!(
+            inst(HeightRounding::default())
+                .get_rows(&ratios),
+            [(3, 266.0)]
+        );
+        assert_eq!(
+            inst(HeightRounding::Round).get_rows(&ratios),
+            [(3, 267.0)]
+        );
+        let exact =
+            inst(HeightRounding::Exact).get_rows(&ratios);
+        assert_eq!(exact.len(), 1);
+        assert!((exact[0].1 - 800.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn exact_rounding_accepts_rows_fitting_exactly() {
+        let inst = ImageGrid {
+            available_width: 1000.0,
+            gap: 3.0,
+            min_line_height: 50.0,
+            max_line_height: 500.0,
+            min_item_width: 10.0,
+            rounding: HeightRounding::Exact,
+            ..Default::default()
+        };
+        let ratios =
+            [0.1, 0.7, 0.2, 1.3, 0.3, 0.9, 0.1, 0.6];
+
+        assert!(inst
+            .heights_for_breaks(&ratios, &[0])
+            .is_ok());
+        assert_eq!(inst.get_rows(&ratios).len(), 1);
+    }
+
+    #[test]
+    fn fit_grid_aspect_ignores_zero_ratios() {
+        let grid = base_grid();
+        let ratios = [1.0, 0.0, 1.0, 1.0];
+
+        let max_line_height =
+            grid.fit_grid_aspect(&ratios, 2.0);
+        let (best, _) =
+            grid.best_fit_total_height(&ratios, 400.0);
+
+        assert!(max_line_height.is_finite());
+        assert!(best.is_finite());
+    }
+
+    #[test]
+    fn fixed_column_grid_survives_invalid_bounds() {
+        let inverted = ImageGrid {
+            available_width: 800.0,
+            min_line_height: 300.0,
+            max_line_height: 200.0,
+            ..Default::default()
+        };
+        let nan = ImageGrid {
+            min_line_height: f64::NAN,
+            max_line_height: 400.0,
+            ..inverted.clone()
+        };
+
+        let layout =
+            inverted.get_fixed_column_grid(&[1.0; 3], 2);
+        assert!(layout
+            .items
+            .iter()
+            .all(|item| item.height == 200.0));
+        let layout =
+            nan.get_fixed_column_grid(&[1.0; 3], 2);
+        assert!(layout
+            .items
+            .iter()
+            .all(|item| item.height == 400.0));
+    }
+
+    #[test]
+    fn timed_layout_charges_the_scale_search() {
+        let grid = ImageGrid {
+            max_rows: Some(10),
+            overflow_policy:
+                OverflowPolicy::JustifyThenScale,
+            ..base_grid()
+        };
+        let ratios = [1.0; 200];
+        // One count plus 32 bisection steps over every item
+        let search_ops = 200 * 33;
+
+        assert_eq!(
+            grid.get_rows_timed(&ratios, 1),
+            (vec![], false)
+        );
+        assert_eq!(
+            grid.get_rows_timed(&ratios, search_ops),
+            (vec![], false)
+        );
+        let (rows, completed) =
+            grid.get_rows_timed(&ratios, search_ops + 1);
+        assert_eq!(rows.len(), 1);
+        assert!(!completed);
+        assert_eq!(
+            grid.get_rows_timed(&ratios, search_ops + 1000),
+            (grid.get_rows(&ratios), true)
+        );
+    }
+
+    #[test]
+    fn layout_report_applies_nan_policy() {
+        let ratios = [1.0, f64::NAN, 1.0, 1.0, 1.0, 1.0];
+        let skipping = ImageGrid {
+            nan_policy: NanPolicy::Skip,
+            ..base_grid()
+        };
+
+        let report = skipping.layout_report(&ratios);
+        assert_eq!(report.skipped, [1]);
+        assert_eq!(report.stats.item_count, 5);
+        assert_eq!(report.rows.len(), 2);
+
+        let report = base_grid().layout_report(&ratios);
+        assert!(report.rows.is_empty());
+        assert_eq!(
+            report.errors,
+            [ConstraintViolation {
+                row: None,
+                error: ResizeError::NanRatio,
+            }]
+        );
+    }
+
+    #[test]
+    fn post_inset_applies_to_every_row_source() {
+        let grid = |gap, gap_mode| ImageGrid {
+            gap,
+            gap_mode,
+            max_rows: Some(1),
+            ..base_grid()
+        };
+        let post_inset =
+            grid(8.0, GapComputation::PostInset);
+        let gapless = grid(0.0, GapComputation::Inline);
+        let inline = grid(8.0, GapComputation::Inline);
+        let ratios = [1.0; 5];
+
+        assert_eq!(
+            post_inset
+                .get_rows_with_priorities(&ratios, &[]),
+            gapless.get_rows_with_priorities(&ratios, &[])
+        );
+        assert_ne!(
+            post_inset
+                .get_rows_with_priorities(&ratios, &[]),
+            inline.get_rows_with_priorities(&ratios, &[])
+        );
+
+        let unbounded = |grid: ImageGrid| ImageGrid {
+            max_rows: None,
+            ..grid
+        };
+        let post_inset = unbounded(post_inset);
+        let gapless = unbounded(gapless);
+        assert_eq!(
+            post_inset.get_rows_optimal_with_cost(&ratios),
+            gapless.get_rows_optimal_with_cost(&ratios)
+        );
+        assert_eq!(
+            post_inset.get_rows_densest(&ratios),
+            gapless.get_rows_densest(&ratios)
+        );
+        assert_ne!(
+            post_inset.get_rows_densest(&ratios),
+            unbounded(inline).get_rows_densest(&ratios)
+        );
+    }
+
+    #[test]
+    fn css_grid_row_spans_sum_to_the_column_count() {
+        // Exact spans 8.6, 8.6 and 6.8 would round to 9 + 9 + 7 = 25
+        let ratios =
+            [43.0 / 30.0, 43.0 / 30.0, 34.0 / 30.0];
+        let inst = ImageGrid {
+            rounding: HeightRounding::Exact,
+            ..base_grid()
+        };
+        let css = inst.to_css_grid(&ratios);
+
+        assert!(css.starts_with(".perfect-grid {\n"));
+        let spans: Vec<u32> = css
+            .lines()
+            .filter_map(|line| line.split("span ").nth(1))
+            .map(|span| {
+                span.trim_end_matches("; }")
+                    .parse()
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(spans, vec![9, 8, 7]);
+        assert_eq!(
+            spans.iter().sum::<u32>(),
+            ImageGrid::CSS_GRID_COLUMNS
         );
     }
 }
@@ -3,6 +3,9 @@
 #![cfg(target_arch = "wasm32")]
 
 extern crate wasm_bindgen_test;
+use js_sys::{Array, Object, Reflect};
+use perfect_grid::*;
+use wasm_bindgen::JsValue;
 use wasm_bindgen_test::*;
 
 wasm_bindgen_test_configure!(run_in_browser);
@@ -11,3 +14,573 @@ wasm_bindgen_test_configure!(run_in_browser);
 fn pass() {
     assert_eq!(1 + 1, 2);
 }
+
+fn config(available_width: f64, gap: f64) -> JsValue {
+    let config = Object::new();
+    for (key, value) in [
+        ("available_width", available_width),
+        ("min_line_height", 200.0),
+        ("max_line_height", 500.0),
+        ("min_item_width", 180.0),
+        ("gap", gap),
+    ] {
+        Reflect::set(&config, &key.into(), &value.into())
+            .unwrap();
+    }
+    config.into()
+}
+
+#[wasm_bindgen_test]
+fn debug_report_has_expected_keys() {
+    let report = get_optimal_grid_debug(
+        vec![1.0; 5],
+        config(800.0, 0.0),
+    )
+    .unwrap();
+
+    for key in ["rows", "stats", "errors"] {
+        assert!(Reflect::has(&report, &key.into()).unwrap());
+    }
+    let rows = Array::from(
+        &Reflect::get(&report, &"rows".into()).unwrap(),
+    );
+    assert_eq!(rows.length(), 2);
+}
+
+#[wasm_bindgen_test]
+fn epsilon_changes_borderline_row() {
+    let heights = |epsilon: f64| {
+        get_optimal_grid(
+            vec![1.0; 5],
+            800.0,
+            200.0,
+            500.0,
+            200.5,
+            0.0,
+            Some(epsilon),
+            None,
+            JsValue::UNDEFINED,
+        )
+        .unwrap()
+        .iter()
+        .map(|height| height.as_f64().unwrap())
+        .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        heights(0.0),
+        [266.0, 266.0, 266.0, 400.0, 400.0]
+    );
+    assert_eq!(
+        heights(1.0),
+        [200.0, 200.0, 200.0, 200.0, 500.0]
+    );
+}
+
+#[wasm_bindgen_test]
+fn separators_split_rows() {
+    let ratios = vec![1.0; 5];
+    let flat = get_optimal_grid(
+        ratios.clone(),
+        800.0,
+        200.0,
+        500.0,
+        180.0,
+        0.0,
+        None,
+        None,
+        JsValue::UNDEFINED,
+    )
+    .unwrap()
+    .iter()
+    .map(|height| height.as_f64().unwrap())
+    .collect::<Vec<_>>();
+    let separated = get_optimal_grid_with_separators(
+        ratios, 800.0, 200.0, 500.0, 180.0, 0.0, None, None,
+    )
+    .unwrap()
+    .iter()
+    .map(|height| height.as_f64().unwrap())
+    .collect::<Vec<_>>();
+
+    let sentinels = separated
+        .iter()
+        .filter(|&&height| height == -1.0)
+        .count();
+    assert_eq!(sentinels, 1);
+    assert_eq!(
+        separated
+            .into_iter()
+            .filter(|&height| height != -1.0)
+            .collect::<Vec<_>>(),
+        flat
+    );
+}
+
+#[wasm_bindgen_test]
+fn row_count_matches_rows() {
+    for ratios in [vec![1.0; 5], vec![1.0; 13], vec![]] {
+        let report = get_optimal_grid_debug(
+            ratios.clone(),
+            config(800.0, 0.0),
+        )
+        .unwrap();
+        let rows = Array::from(
+            &Reflect::get(&report, &"rows".into()).unwrap(),
+        );
+
+        assert_eq!(
+            get_row_count(ratios, config(800.0, 0.0))
+                .unwrap(),
+            rows.length()
+        );
+    }
+}
+
+#[wasm_bindgen_test]
+fn layout_has_item_geometry() {
+    let layout = get_optimal_grid_layout(
+        vec![1.0; 5],
+        800.0,
+        200.0,
+        500.0,
+        180.0,
+        10.0,
+        20.0,
+        None,
+    )
+    .unwrap();
+
+    assert_eq!(layout.len(), 5);
+    assert_eq!((layout[0].x, layout[0].y), (0.0, 0.0));
+    assert_eq!(layout[1].x, layout[0].width + 10.0);
+    assert_eq!(layout[3].x, 0.0);
+    assert_eq!(layout[3].y, layout[0].height + 20.0);
+    for item in &layout {
+        assert_eq!(item.width, item.height);
+    }
+}
+
+#[wasm_bindgen_test]
+fn invalid_bounds_throw_readable_error() {
+    let error = get_optimal_grid(
+        vec![1.0; 5],
+        800.0,
+        500.0,
+        200.0,
+        180.0,
+        0.0,
+        None,
+        None,
+        JsValue::UNDEFINED,
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        String::from(js_sys::Error::from(error).message()),
+        ResizeError::InvalidHeightBounds.to_string()
+    );
+    assert!(get_optimal_grid_layout(
+        vec![1.0; 5],
+        800.0,
+        500.0,
+        200.0,
+        180.0,
+        0.0,
+        0.0,
+        None
+    )
+    .is_err());
+    assert!(get_optimal_grid_soa(
+        vec![1.0; 5],
+        800.0,
+        500.0,
+        200.0,
+        180.0,
+        0.0,
+        None
+    )
+    .is_err());
+    assert!(get_optimal_grid_with_separators(
+        vec![1.0; 5],
+        800.0,
+        500.0,
+        200.0,
+        180.0,
+        0.0,
+        None,
+        None
+    )
+    .is_err());
+    assert!(get_row_count(
+        vec![1.0; 5],
+        config(100.0, 0.0)
+    )
+    .is_err());
+    assert!(get_optimal_grid_debug(
+        vec![1.0; 5],
+        config(100.0, 0.0)
+    )
+    .is_err());
+}
+
+#[wasm_bindgen_test]
+fn empty_ratios_throw_empty_error() {
+    let error = get_optimal_grid(
+        vec![],
+        800.0,
+        200.0,
+        500.0,
+        180.0,
+        0.0,
+        None,
+        None,
+        JsValue::UNDEFINED,
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        String::from(js_sys::Error::from(error).message()),
+        ResizeError::Empty.to_string()
+    );
+    assert!(get_optimal_grid_with_separators(
+        vec![],
+        800.0,
+        200.0,
+        500.0,
+        180.0,
+        0.0,
+        None,
+        None
+    )
+    .is_err());
+    assert!(get_optimal_grid_layout(
+        vec![],
+        800.0,
+        200.0,
+        500.0,
+        180.0,
+        0.0,
+        0.0,
+        None
+    )
+    .is_err());
+    assert!(get_optimal_grid_soa(
+        vec![],
+        800.0,
+        200.0,
+        500.0,
+        180.0,
+        0.0,
+        None
+    )
+    .is_err());
+    assert!(get_grid_rows(
+        vec![],
+        800.0,
+        200.0,
+        500.0,
+        180.0,
+        0.0,
+        None
+    )
+    .is_err());
+    assert!(get_grid_total_height(
+        vec![],
+        800.0,
+        200.0,
+        500.0,
+        180.0,
+        0.0,
+        0.0,
+        None
+    )
+    .is_err());
+    assert_eq!(
+        get_row_count(vec![], config(800.0, 0.0)),
+        Ok(0)
+    );
+}
+
+#[wasm_bindgen_test]
+fn max_items_per_row_forces_breaks() {
+    let heights = get_optimal_grid(
+        vec![1.0; 5],
+        800.0,
+        100.0,
+        500.0,
+        100.0,
+        0.0,
+        None,
+        Some(2),
+        JsValue::UNDEFINED,
+    )
+    .unwrap()
+    .iter()
+    .map(|height| height.as_f64().unwrap())
+    .collect::<Vec<_>>();
+
+    assert_eq!(
+        heights,
+        [400.0, 400.0, 400.0, 400.0, 500.0]
+    );
+}
+
+#[wasm_bindgen_test]
+fn total_height_includes_row_gap() {
+    let height = get_grid_total_height(
+        vec![1.0; 5],
+        800.0,
+        200.0,
+        500.0,
+        180.0,
+        0.0,
+        10.0,
+        None,
+    )
+    .unwrap();
+
+    assert_eq!(height, 710.0);
+}
+
+#[wasm_bindgen_test]
+fn config_sets_last_row_behavior() {
+    let config = config(800.0, 0.0);
+    Reflect::set(
+        &config,
+        &"last_row".into(),
+        &"MatchPrevious".into(),
+    )
+    .unwrap();
+    let report =
+        get_optimal_grid_debug(vec![1.0; 5], config)
+            .unwrap();
+    let rows = Array::from(
+        &Reflect::get(&report, &"rows".into()).unwrap(),
+    );
+    let height = |row: u32| {
+        Reflect::get(&rows.get(row), &"height".into())
+            .unwrap()
+            .as_f64()
+            .unwrap()
+    };
+
+    assert_eq!(height(1), height(0));
+}
+
+#[wasm_bindgen_test]
+fn config_sets_last_row_justify_threshold() {
+    let ratios = vec![1.0, 1.0, 1.0, 1.0, 1.0, 1.5];
+    let last_height = |config: JsValue| {
+        let report =
+            get_optimal_grid_debug(ratios.clone(), config)
+                .unwrap();
+        let rows = Array::from(
+            &Reflect::get(&report, &"rows".into()).unwrap(),
+        );
+        Reflect::get(&rows.get(1), &"height".into())
+            .unwrap()
+            .as_f64()
+            .unwrap()
+    };
+    let sparse = config(1000.0, 0.0);
+    Reflect::set(
+        &sparse,
+        &"last_row_justify_threshold".into(),
+        &0.6.into(),
+    )
+    .unwrap();
+
+    assert_eq!(last_height(config(1000.0, 0.0)), 500.0);
+    assert_eq!(last_height(sparse), 200.0);
+}
+
+#[wasm_bindgen_test]
+fn fixed_column_grid_export() {
+    let layout = get_fixed_column_grid(
+        vec![1.0, 0.25, 1.0],
+        620.0,
+        100.0,
+        400.0,
+        10.0,
+        3,
+        None,
+    )
+    .unwrap();
+    let items = Array::from(
+        &Reflect::get(&layout, &"items".into()).unwrap(),
+    );
+    let clamped = Array::from(
+        &Reflect::get(&layout, &"clamped".into()).unwrap(),
+    );
+
+    assert_eq!(items.length(), 3);
+    assert_eq!(clamped.length(), 1);
+    assert_eq!(clamped.get(0).as_f64(), Some(1.0));
+}
+
+#[wasm_bindgen_test]
+fn grid_rows_keep_input_indices() {
+    let rows = Array::from(
+        &get_grid_rows(
+            vec![1.0; 9],
+            800.0,
+            200.0,
+            500.0,
+            180.0,
+            0.0,
+            None,
+        )
+        .unwrap(),
+    );
+    let field = |row: u32, key: &str| {
+        Reflect::get(&rows.get(row), &key.into())
+            .unwrap()
+            .as_f64()
+            .unwrap()
+    };
+
+    assert_eq!(rows.length(), 3);
+    assert_eq!(
+        (0..3)
+            .map(|row| field(row, "start_index"))
+            .collect::<Vec<_>>(),
+        [0.0, 4.0, 8.0]
+    );
+    assert_eq!(
+        (field(2, "count"), field(2, "height")),
+        (1.0, 500.0)
+    );
+    assert_eq!(
+        get_row_count(vec![1.0; 9], config(800.0, 0.0))
+            .unwrap(),
+        rows.length()
+    );
+}
+
+#[wasm_bindgen_test]
+fn nan_ratio_throws_nan_error() {
+    let ratios = vec![1.0, f64::NAN, 1.0];
+    let error = get_optimal_grid(
+        ratios.clone(),
+        800.0,
+        200.0,
+        500.0,
+        180.0,
+        0.0,
+        None,
+        None,
+        JsValue::UNDEFINED,
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        String::from(js_sys::Error::from(error).message()),
+        ResizeError::NanRatio.to_string()
+    );
+    assert!(get_row_count(
+        ratios.clone(),
+        config(800.0, 0.0)
+    )
+    .is_err());
+    assert!(get_grid_rows(
+        ratios, 800.0, 200.0, 500.0, 180.0, 0.0, None
+    )
+    .is_err());
+}
+
+#[wasm_bindgen_test]
+fn last_row_param_sets_behavior() {
+    let heights = get_optimal_grid(
+        vec![1.0; 5],
+        800.0,
+        200.0,
+        500.0,
+        180.0,
+        0.0,
+        None,
+        None,
+        "MatchPrevious".into(),
+    )
+    .unwrap()
+    .iter()
+    .map(|height| height.as_f64().unwrap())
+    .collect::<Vec<_>>();
+
+    assert_eq!(heights, [200.0; 5]);
+}
+
+#[wasm_bindgen_test]
+fn exports_share_epsilon() {
+    // Heights of the first row as seen by three exports
+    let first_heights = |epsilon: f64| {
+        let rows = get_grid_rows(
+            vec![1.0; 5],
+            800.0,
+            200.0,
+            500.0,
+            200.5,
+            0.0,
+            Some(epsilon),
+        )
+        .unwrap();
+        let separated = get_optimal_grid_with_separators(
+            vec![1.0; 5],
+            800.0,
+            200.0,
+            500.0,
+            200.5,
+            0.0,
+            None,
+            Some(epsilon),
+        )
+        .unwrap();
+        let layout = get_optimal_grid_layout(
+            vec![1.0; 5],
+            800.0,
+            200.0,
+            500.0,
+            200.5,
+            0.0,
+            0.0,
+            Some(epsilon),
+        )
+        .unwrap();
+        let row = Array::from(&rows).get(0);
+        (
+            Reflect::get(&row, &"height".into())
+                .unwrap()
+                .as_f64()
+                .unwrap(),
+            separated.get(0).as_f64().unwrap(),
+            layout[0].height,
+        )
+    };
+
+    assert_eq!(first_heights(0.0), (266.0, 266.0, 266.0));
+    assert_eq!(first_heights(1.0), (200.0, 200.0, 200.0));
+}
+
+#[wasm_bindgen_test]
+fn config_sets_nan_policy() {
+    let ratios = vec![1.0, f64::NAN, 1.0, 1.0, 1.0, 1.0];
+    let config = config(800.0, 0.0);
+    Reflect::set(
+        &config,
+        &"nan_policy".into(),
+        &"Skip".into(),
+    )
+    .unwrap();
+    let report = get_optimal_grid_debug(
+        ratios.clone(),
+        config.clone(),
+    )
+    .unwrap();
+    let skipped = Array::from(
+        &Reflect::get(&report, &"skipped".into()).unwrap(),
+    );
+
+    assert_eq!(skipped.length(), 1);
+    assert_eq!(skipped.get(0).as_f64(), Some(1.0));
+    assert_eq!(get_row_count(ratios, config), Ok(2));
+}